@@ -3,18 +3,21 @@ use std::sync::Mutex;
 use std::sync::atomic::Ordering;
 use std::thread;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rust_synthesize_engine::common_types::Waveform;
+use rust_synthesize_engine::synth::channel::NoteEvent;
 
 use crate::audio_stream::*;
+use crate::live_voices::VoiceBank;
+use crate::sequencer::Sequencer;
 use crate::common_types::CommandType;
 use crate::common_types::ProcessStatus;
 use crate::wav_reader::*;
-use crate::utils::set_status;
+use crate::utils::{end_job, set_status};
 use crate::global_state::*;
 
 
-pub fn de_init() {
+pub fn de_init(job_id: u32) {
     thread::spawn(move || {
-        set_status(ProcessStatus::InProgress, CommandType::DeInit);
 
         PLAYBACK_STATUS.store(false, Ordering::SeqCst);
 
@@ -22,7 +25,7 @@ pub fn de_init() {
             let mut stream_lock = match stream_arc.lock() {
                 Ok(o) => o,
                 Err(_) => {
-                    set_status(ProcessStatus::Error, CommandType::None);
+                    end_job(job_id, ProcessStatus::Error, Some("audio stream unavailable"));
                     return;
                 },
             };
@@ -34,7 +37,7 @@ pub fn de_init() {
                 Ok(o) => o,
                 Err(_) => {
                     
-                    set_status(ProcessStatus::Error, CommandType::None);
+                    end_job(job_id, ProcessStatus::Error, Some("playback buffer unavailable"));
                     return;
                 },
             };
@@ -46,7 +49,7 @@ pub fn de_init() {
                 Ok(o) => o,
                 Err(_) => {
                     
-                    set_status(ProcessStatus::Error, CommandType::None);
+                    end_job(job_id, ProcessStatus::Error, Some("playback position unavailable"));
                     return;
                 },
             };
@@ -54,21 +57,21 @@ pub fn de_init() {
         }
 
         
-        set_status(ProcessStatus::Success, CommandType::None);
+        end_job(job_id, ProcessStatus::Success, None);
     });
     
 }
 
 
-pub fn init() {
+pub fn init(job_id: u32) {
 
     thread::spawn(move || {
-                    
-        set_status(ProcessStatus::InProgress, CommandType::Init);
-        
+
 
         AUDIO_BUFFER.get_or_init(|| Arc::new(Mutex::new(Vec::new())));
         AUDIO_POSITION.get_or_init(|| Arc::new(Mutex::new(0)));
+        VOICE_BANK.get_or_init(|| Arc::new(Mutex::new(VoiceBank::new())));
+        SEQUENCER.get_or_init(|| Arc::new(Mutex::new(Sequencer::new())));
 
 
         let host = cpal::default_host();
@@ -77,7 +80,7 @@ pub fn init() {
             Some(d) => d,
             None => {
                     
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("no audio output device"));
                 return;
             },
         };
@@ -87,7 +90,7 @@ pub fn init() {
             Ok(cfg) => cfg.config(),
             Err(_) => {
                     
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("output device configuration unavailable"));
                 return;
             },
         };
@@ -97,7 +100,7 @@ pub fn init() {
             Some(b) => Arc::clone(b),
             None => {
                     
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("playback buffer unavailable"));
                 return;
             },
         };
@@ -107,7 +110,25 @@ pub fn init() {
             Some(p) => Arc::clone(p),
             None => {
                     
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("playback position unavailable"));
+                return;
+            },
+        };
+
+
+        let voices = match VOICE_BANK.get() {
+            Some(v) => Arc::clone(v),
+            None => {
+                end_job(job_id, ProcessStatus::Error, Some("voice bank unavailable"));
+                return;
+            },
+        };
+
+
+        let sequencer = match SEQUENCER.get() {
+            Some(s) => Arc::clone(s),
+            None => {
+                end_job(job_id, ProcessStatus::Error, Some("sequencer unavailable"));
                 return;
             },
         };
@@ -117,12 +138,14 @@ pub fn init() {
             &device,
             &supported_config,
             Arc::clone(&buffer),
-            Arc::clone(&pos)
+            Arc::clone(&pos),
+            Arc::clone(&voices),
+            Arc::clone(&sequencer)
         ) {
             Ok(s) => s,
             Err(_) => {
                     
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("output stream could not be created"));
                 return;
             },
         };
@@ -130,7 +153,7 @@ pub fn init() {
 
         if let Err(_) = stream.play() {
                     
-            set_status(ProcessStatus::Error, CommandType::None);
+            end_job(job_id, ProcessStatus::Error, Some("output stream could not be started"));
             return;
         }
 
@@ -138,26 +161,25 @@ pub fn init() {
 
         if update_global_stream(stream) != 0 {
                     
-            set_status(ProcessStatus::Error, CommandType::None);
+            end_job(job_id, ProcessStatus::Error, Some("output stream could not be stored"));
             return;
         }
 
 
-        set_status(ProcessStatus::Success, CommandType::None);
+        end_job(job_id, ProcessStatus::Success, None);
     });
 }
 
 
-pub fn set_sample_rate(new_rate: u32) {
+pub fn set_sample_rate(new_rate: u32, job_id: u32) {
     
     thread::spawn(move || {
-        set_status(ProcessStatus::InProgress, CommandType::SetSampleRate);
 
         if let Some(stream_arc) = STREAM.get() {
             match stream_arc.lock() {
                 Ok(mut stream_lock) => *stream_lock = None,
                 Err(_) => {
-                    set_status(ProcessStatus::Error, CommandType::None);
+                    end_job(job_id, ProcessStatus::Error, Some("audio stream unavailable"));
                     return;
                 },
             }
@@ -167,7 +189,7 @@ pub fn set_sample_rate(new_rate: u32) {
         let device = match host.default_output_device() {
             Some(d) => d,
             None => {
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("no audio output device"));
                 return;
             },
         };
@@ -175,7 +197,7 @@ pub fn set_sample_rate(new_rate: u32) {
         let mut supported_config = match device.default_output_config() {
             Ok(cfg) => cfg.config(),
             Err(_) => {
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("output device configuration unavailable"));
                 return;
             },
         };
@@ -186,44 +208,48 @@ pub fn set_sample_rate(new_rate: u32) {
         let buffer = match AUDIO_BUFFER.get() {
             Some(b) => Arc::clone(b),
             None => {
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("playback buffer unavailable"));
                 return;
             },
         };
         let pos = match AUDIO_POSITION.get() {
             Some(p) => Arc::clone(p),
             None => {
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("playback position unavailable"));
                 return;
             },
         };
+        let voices = VOICE_BANK.get_or_init(|| Arc::new(Mutex::new(VoiceBank::new())));
+        let sequencer = SEQUENCER.get_or_init(|| Arc::new(Mutex::new(Sequencer::new())));
 
         let stream = match build_output_stream(
             &device,
             &supported_config,
             Arc::clone(&buffer),
-            Arc::clone(&pos)
+            Arc::clone(&pos),
+            Arc::clone(voices),
+            Arc::clone(sequencer)
         ) {
             Ok(s) => s,
             Err(_) => {
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("output stream could not be created"));
                 return;
             },
         };
 
         if let Err(_) = stream.play() {
-            set_status(ProcessStatus::Error, CommandType::None);
+            end_job(job_id, ProcessStatus::Error, Some("output stream could not be started"));
             return;
         }
         
 
         if update_global_stream(stream) != 0 {
-            set_status(ProcessStatus::Error, CommandType::None);
+            end_job(job_id, ProcessStatus::Error, Some("output stream could not be stored"));
             return;
         }
 
 
-        set_status(ProcessStatus::Success, CommandType::None);
+        end_job(job_id, ProcessStatus::Success, None);
     });
     
 }
@@ -240,64 +266,188 @@ pub fn stop_audio(){
 }
 
 
+fn load_samples(
+    samples: Vec<f32>,
+    sample_rate: u32,
+    channel_count: usize,
+    milliseconds_position: u32
+) -> bool {
+
+    let buffer = AUDIO_BUFFER.get_or_init(|| Arc::new(Mutex::new(Vec::new())));
+
+    let mut buf = match buffer.lock(){
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+
+    *buf = samples;
+
+    let pos = AUDIO_POSITION.get_or_init(|| Arc::new(Mutex::new(0)));
+
+    let mut pos_guard = match pos.lock() {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+
+
+    let frame_pos = ((milliseconds_position as f64 / 1000.0)
+        * sample_rate as f64) as usize;
+
+    let target_pos = frame_pos * channel_count;
+
+    *pos_guard = target_pos.min(buf.len());
+
+    PLAYBACK_STATUS.store(true, Ordering::SeqCst);
+
+    true
+}
+
+
 pub fn play_audio(
     path: String,
-    milliseconds_position : u32
+    milliseconds_position : u32,
+    job_id: u32
 ) {
 
     thread::spawn(move || {
 
-        set_status(ProcessStatus::InProgress, CommandType::Play);
-
         let wav_file_data = match read_wav_file(path)
         {
             Some(o) => o,
             None => {
-                set_status(ProcessStatus::Error, CommandType::None);
+                end_job(job_id, ProcessStatus::Error, Some("WAV file could not be read"));
                 return;
             }
         };
 
-        let buffer = AUDIO_BUFFER.get_or_init(|| Arc::new(Mutex::new(Vec::new())));
+        let status: bool = load_samples(
+            wav_file_data.samples,
+            wav_file_data.sample_rate,
+            wav_file_data.channel_count as usize,
+            milliseconds_position
+        );
+
+        if status {
+            end_job(job_id, ProcessStatus::Success, None);
+        } else {
+            end_job(job_id, ProcessStatus::Error, Some("playback buffer unavailable"));
+        }
+
+    });
+
     
-        let mut buf = match buffer.lock(){
-            Ok(o) => o,
-            Err(_) => {
-                set_status(ProcessStatus::Error, CommandType::None);
-                return;
-            }
-        };
+}
 
-        *buf = wav_file_data.samples;
 
-        let pos = AUDIO_POSITION.get_or_init(|| Arc::new(Mutex::new(0)));
+pub fn play_samples(
+    samples: Vec<f32>,
+    sample_rate: u32,
+    channel_count: usize,
+    milliseconds_position: u32,
+    job_id: u32
+) {
 
-        let mut pos_guard = match pos.lock() {
-            Ok(p) => p,
-            Err(_) => {
-                set_status(ProcessStatus::Error, CommandType::None);
-                return;
-            }
-        };
+    thread::spawn(move || {
 
+        let status: bool = load_samples(
+            samples,
+            sample_rate,
+            channel_count,
+            milliseconds_position
+        );
+
+        if status {
+            end_job(job_id, ProcessStatus::Success, None);
+        } else {
+            end_job(job_id, ProcessStatus::Error, Some("playback buffer unavailable"));
+        }
 
-        let channels = wav_file_data.channel_count as usize;
+    });
 
-        let frame_pos = ((milliseconds_position as f64 / 1000.0)
-            * wav_file_data.sample_rate as f64) as usize;
+}
 
-        let target_pos = frame_pos * channels;
 
-        *pos_guard = target_pos.min(buf.len());
 
-    
+pub fn note_on(
+    note: String,
+    waveform: Waveform,
+    frequency: f32,
+    gain: f32
+) {
+    set_status(ProcessStatus::InProgress, CommandType::NoteOn);
 
-        PLAYBACK_STATUS.store(true, Ordering::SeqCst);
+    let voices = VOICE_BANK.get_or_init(|| Arc::new(Mutex::new(VoiceBank::new())));
 
+    match voices.lock() {
+        Ok(mut bank) => {
+            bank.note_on(note, waveform, frequency, gain);
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
 
-        set_status(ProcessStatus::Success, CommandType::None);
+pub fn note_off(note: String) {
+    set_status(ProcessStatus::InProgress, CommandType::NoteOff);
 
-    });
+    let voices = VOICE_BANK.get_or_init(|| Arc::new(Mutex::new(VoiceBank::new())));
 
-    
-}
\ No newline at end of file
+    match voices.lock() {
+        Ok(mut bank) => {
+            bank.note_off(&note);
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+pub fn all_notes_off() {
+    set_status(ProcessStatus::InProgress, CommandType::NoteOff);
+
+    let voices = VOICE_BANK.get_or_init(|| Arc::new(Mutex::new(VoiceBank::new())));
+
+    match voices.lock() {
+        Ok(mut bank) => {
+            bank.all_notes_off();
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+
+
+pub fn sequencer_load(song: Vec<Vec<Vec<NoteEvent>>>) {
+    set_status(ProcessStatus::InProgress, CommandType::SequencerLoad);
+
+    let sequencer = SEQUENCER.get_or_init(|| Arc::new(Mutex::new(Sequencer::new())));
+
+    match sequencer.lock() {
+        Ok(mut s) => {
+            s.load(song);
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+pub fn sequencer_transport(action: impl FnOnce(&mut Sequencer)) {
+    set_status(ProcessStatus::InProgress, CommandType::SequencerTransport);
+
+    let sequencer = SEQUENCER.get_or_init(|| Arc::new(Mutex::new(Sequencer::new())));
+
+    match sequencer.lock() {
+        Ok(mut s) => {
+            action(&mut s);
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+pub fn sequencer_position() -> u32 {
+    match SEQUENCER.get().map(|s| s.lock()) {
+        Some(Ok(s)) => s.position_milliseconds(),
+        _ => 0,
+    }
+}
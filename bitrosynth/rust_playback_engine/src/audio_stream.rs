@@ -3,6 +3,8 @@ use std::sync::{Arc, Mutex, atomic::Ordering};
 
 use crate::global_state::STREAM;
 use crate::global_state::PLAYBACK_STATUS;
+use crate::live_voices::VoiceBank;
+use crate::sequencer::Sequencer;
 
 
 
@@ -11,12 +13,19 @@ pub fn build_output_stream(
     config: &cpal::StreamConfig,
     buffer: Arc<Mutex<Vec<f32>>>,
     pos: Arc<Mutex<usize>>,
+    voices: Arc<Mutex<VoiceBank>>,
+    sequencer: Arc<Mutex<Sequencer>>,
 ) -> Result<cpal::Stream, ()> {
+    let channel_count: usize = (config.channels as usize).max(1);
+    let sample_rate: f32 = config.sample_rate as f32;
+
     device.build_output_stream(
         config,
         {
             let buffer = Arc::clone(&buffer);
             let pos = Arc::clone(&pos);
+            let voices = Arc::clone(&voices);
+            let sequencer = Arc::clone(&sequencer);
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
 
                 let unlocked_buffer = match buffer.lock() {
@@ -27,24 +36,48 @@ pub fn build_output_stream(
                     Ok(p) => p,
                     Err(_) => return,
                 };
+                let mut unlocked_voices = match voices.lock() {
+                    Ok(v) => v,
+                    Err(_) => return,
+                };
+                let mut unlocked_sequencer = match sequencer.lock() {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+
+                for frame in data.chunks_mut(channel_count) {
+
+                    // live voices and the sequencer are mono and land on every output channel
+                    let mut voice_sample: f32 = if unlocked_voices.is_silent() {
+                        0.0
+                    } else {
+                        unlocked_voices.next_sample(sample_rate)
+                    };
+
+                    if unlocked_sequencer.is_playing() {
+                        voice_sample += unlocked_sequencer.next_sample(sample_rate);
+                    }
 
-                for sample in data.iter_mut() {
+                    for sample in frame.iter_mut() {
 
-                    if PLAYBACK_STATUS.load(Ordering::SeqCst) {
+                        if PLAYBACK_STATUS.load(Ordering::SeqCst) {
 
-                        if *unlocked_pos < unlocked_buffer.len() {
+                            if *unlocked_pos < unlocked_buffer.len() {
 
-                            *sample = unlocked_buffer[*unlocked_pos];
-                            *unlocked_pos += 1;
+                                *sample = unlocked_buffer[*unlocked_pos];
+                                *unlocked_pos += 1;
 
-                        } else {
+                            } else {
 
-                            *sample = 0.0;
+                                *sample = 0.0;
 
+                            }
+
+                        } else {
+                         *sample = 0.0;
                         }
 
-                    } else {
-                     *sample = 0.0;
+                        *sample = (*sample + voice_sample).clamp(-1.0, 1.0);
                     }
 
                 }
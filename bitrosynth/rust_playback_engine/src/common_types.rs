@@ -1,14 +1,26 @@
 #[repr(i32)]
+#[derive(Clone, Copy)]
 pub enum CommandType {
     None = 0,
     Init = 1,
     Play = 2,
     Stop = 3,
     DeInit = 4,
-    SetSampleRate = 5
+    SetSampleRate = 5,
+    NoteOn = 6,
+    NoteOff = 7,
+    SequencerLoad = 8,
+    SequencerTransport = 9,
+    RegisterInstrument = 10,
+    RemoveInstrument = 11,
+    ClearInstruments = 12,
+    LoadSample = 13,
+    ClearSamples = 14,
+    LoadProjectSounds = 15
 }
 
 #[repr(i32)]
+#[derive(Clone, Copy)]
 pub enum ProcessStatus {
     InProgress = 1,
     Success = 0,
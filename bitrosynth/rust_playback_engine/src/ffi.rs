@@ -1,6 +1,14 @@
-use std::ffi::{CStr, c_char, c_int, c_uint};
+use std::ffi::{CStr, c_char, c_float, c_int, c_uchar, c_uint};
 use crate::global_state::{CURRENT_STATUS,CURRENT_COMMAND};
-use crate::{common_types::{CommandType, ProcessStatus}, utils::set_status};
+use crate::{common_types::{CommandType, ProcessStatus}, utils::{begin_job, end_job, set_status}};
+use rust_synthesize_engine::jobs::{self, active_job_count, job_command, job_error, job_progress, job_status};
+use rust_synthesize_engine::common_types::Waveform;
+use rust_synthesize_engine::project::Project;
+use rust_synthesize_engine::synth::channel::{NoteEvent, parse_row};
+use rust_synthesize_engine::synth::instrument::{self, Instrument};
+use rust_synthesize_engine::synth::sampler::{self, Sample, SampleFile};
+use rust_synthesize_engine::synth::sound_bank::SoundBank;
+use rust_synthesize_engine::utils::{c_char_to_string, c_song_to_vec, note_to_frequency};
 
 
 // -------------------------------------------------------------
@@ -15,34 +23,70 @@ pub extern "C" fn get_current_command() -> c_int {
     return CURRENT_COMMAND.load(std::sync::atomic::Ordering::SeqCst) as c_int;
 }
 
-// -------------------------------------------------------------
+#[unsafe(no_mangle)]
+pub extern "C" fn get_job_status(job_id: c_uint) -> c_int {
+    job_status(job_id) as c_int
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_job_command(job_id: c_uint) -> c_int {
+    job_command(job_id) as c_int
+}
 
 #[unsafe(no_mangle)]
-pub extern "C" fn init() {
-    crate::audio_control::init();
+pub extern "C" fn get_job_progress(job_id: c_uint) -> c_float {
+    job_progress(job_id)
 }
 
+// Null when the job has no error, the string is owned by the engine until release_job
 #[unsafe(no_mangle)]
-pub extern "C" fn de_init() {
-    crate::audio_control::de_init();
+pub extern "C" fn get_job_error(job_id: c_uint) -> *const c_char {
+    job_error(job_id)
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn release_job(job_id: c_uint) -> c_uchar {
+    jobs::release_job(job_id) as c_uchar
+}
 
 #[unsafe(no_mangle)]
-pub extern "C" fn set_sample_rate(c_sample_rate : c_uint){
+pub extern "C" fn get_active_job_count() -> c_uint {
+    active_job_count()
+}
 
+// -------------------------------------------------------------
+
+// The asynchronous calls below return a job id, see get_job_status
+#[unsafe(no_mangle)]
+pub extern "C" fn init() -> c_uint {
+    let job_id: u32 = begin_job(CommandType::Init);
+    crate::audio_control::init(job_id);
+    job_id
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn de_init() -> c_uint {
+    let job_id: u32 = begin_job(CommandType::DeInit);
+    crate::audio_control::de_init(job_id);
+    job_id
+}
+
+
+#[unsafe(no_mangle)]
+pub extern "C" fn set_sample_rate(c_sample_rate : c_uint) -> c_uint {
 
-    set_status(ProcessStatus::InProgress, CommandType::SetSampleRate);
+    let job_id: u32 = begin_job(CommandType::SetSampleRate);
 
     let new_rate: u32 = match u32::try_from(c_sample_rate){
         Ok(o) => o,
         Err(_) => {
-            set_status(ProcessStatus::Error, CommandType::None);
-            return;
+            end_job(job_id, ProcessStatus::Error, Some("invalid sample rate"));
+            return job_id;
         }
     };
 
-    crate::audio_control::set_sample_rate(new_rate);
+    crate::audio_control::set_sample_rate(new_rate, job_id);
+    job_id
 }
 
 
@@ -50,17 +94,17 @@ pub extern "C" fn set_sample_rate(c_sample_rate : c_uint){
 pub extern "C" fn play_audio(
     c_path: *const c_char,
     c_milliseconds_position : c_uint
-) {
+) -> c_uint {
 
-    set_status(ProcessStatus::InProgress, CommandType::Play);
+    let job_id: u32 = begin_job(CommandType::Play);
                     
 
     let path: String;
 
     if c_path.is_null() {
         
-        set_status(ProcessStatus::Error, CommandType::None);
-        return;
+        end_job(job_id, ProcessStatus::Error, Some("invalid path"));
+        return job_id;
               
     }
 
@@ -69,8 +113,8 @@ pub extern "C" fn play_audio(
         match c_str.to_str() {
             Ok(str_slice) => path = str_slice.to_string(),
             Err(_) => {
-                set_status(ProcessStatus::Error, CommandType::None);  
-                return;
+                end_job(job_id, ProcessStatus::Error, Some("invalid path"));
+                return job_id;
             }
         }
     }
@@ -79,20 +123,281 @@ pub extern "C" fn play_audio(
         Ok(o) => o,
         Err(_) => {
                     
-            set_status(ProcessStatus::Error, CommandType::None);
-            return;
+            end_job(job_id, ProcessStatus::Error, Some("invalid position"));
+            return job_id;
         },
     };
     
 
     crate::audio_control::play_audio(
         path,
-        milliseconds_position
+        milliseconds_position,
+        job_id
     );
-    
+
+    job_id
+}
+
+// Copies the samples, so the caller can free its buffer as soon as this returns
+/// # Safety
+/// c_samples must point to at least c_length floats
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn play_samples(
+    c_samples: *const c_float,
+    c_length: c_uint,
+    c_sample_rate: c_uint,
+    c_channel_count: c_uint,
+    c_milliseconds_position : c_uint
+) -> c_uint {
+
+    let job_id: u32 = begin_job(CommandType::Play);
+
+    if c_samples.is_null() || c_channel_count == 0 {
+        end_job(job_id, ProcessStatus::Error, Some("invalid sample buffer"));
+        return job_id;
+    }
+
+    let samples: Vec<f32> = unsafe {
+        std::slice::from_raw_parts(c_samples, c_length as usize).to_vec()
+    };
+
+    crate::audio_control::play_samples(
+        samples,
+        c_sample_rate,
+        c_channel_count as usize,
+        c_milliseconds_position,
+        job_id
+    );
+
+    job_id
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn stop_audio(){
     crate::audio_control::stop_audio();
-}
\ No newline at end of file
+}
+
+// -------------------------------------------------------------
+
+#[unsafe(no_mangle)]
+pub extern "C" fn note_on(
+    c_note: *const c_char,
+    c_waveform: *const c_char,
+    c_gain: c_float
+) {
+
+    set_status(ProcessStatus::InProgress, CommandType::NoteOn);
+
+    let note: String = match c_char_to_string(c_note) {
+        Some(n) => n.trim().to_ascii_uppercase(),
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    let frequency: f32 = match note_to_frequency(&note) {
+        Some(f) => f,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    let waveform: Waveform = match c_char_to_string(c_waveform)
+        .and_then(|w| Waveform::from_name(&w.replace(' ', "")))
+    {
+        Some(w) => w,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    crate::audio_control::note_on(note, waveform, frequency, c_gain);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn note_off(c_note: *const c_char) {
+
+    set_status(ProcessStatus::InProgress, CommandType::NoteOff);
+
+    let note: String = match c_char_to_string(c_note) {
+        Some(n) => n.trim().to_ascii_uppercase(),
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    crate::audio_control::note_off(note);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn all_notes_off() {
+    crate::audio_control::all_notes_off();
+}
+
+
+// -------------------------------------------------------------
+
+// The playback engine keeps its own instruments and sample bank, they mirror the calls of
+// the synthesize engine and must be set up before sequencer_load or note_on names them
+
+#[unsafe(no_mangle)]
+pub extern "C" fn register_instrument(c_str_json: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::RegisterInstrument);
+
+    let status: bool = c_char_to_string(c_str_json)
+        .and_then(|json| Instrument::from_json(&json))
+        .is_some_and(instrument::register_instrument);
+
+    set_status(
+        if status { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn remove_instrument(c_str_name: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::RemoveInstrument);
+
+    let status: bool = c_char_to_string(c_str_name).is_some_and(|n| instrument::remove_instrument(&n));
+
+    set_status(
+        if status { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_instruments() {
+    set_status(ProcessStatus::InProgress, CommandType::ClearInstruments);
+
+    set_status(
+        if instrument::set_instruments(&[]) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// Returns the slot for SampleN tokens or -1, slots follow the load order as in the synthesize engine
+#[unsafe(no_mangle)]
+pub extern "C" fn load_sample(
+    c_str_path: *const c_char,
+    c_str_root_note: *const c_char,
+    loop_start: c_uint,
+    loop_end: c_uint,
+    one_shot: c_uchar,
+) -> c_int {
+    set_status(ProcessStatus::InProgress, CommandType::LoadSample);
+
+    let file: Option<SampleFile> = match (c_char_to_string(c_str_path), c_char_to_string(c_str_root_note)) {
+        (Some(path), Some(root_note)) => Some(SampleFile {
+            path,
+            root_note,
+            loop_start: loop_start as usize,
+            loop_end: loop_end as usize,
+            one_shot: one_shot != 0,
+            module_sample: None,
+        }),
+        _ => None,
+    };
+
+    match file.and_then(Sample::load).and_then(sampler::add_sample) {
+        Some(slot) => {
+            set_status(ProcessStatus::Success, CommandType::None);
+            slot as c_int
+        }
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            -1
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_samples() {
+    set_status(ProcessStatus::InProgress, CommandType::ClearSamples);
+    sampler::clear_samples();
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+// Loads the instruments and samples of a project file into the same slots the synthesize engine uses
+#[unsafe(no_mangle)]
+pub extern "C" fn load_project_sounds(c_str_path: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::LoadProjectSounds);
+
+    let status: bool = c_char_to_string(c_str_path)
+        .and_then(|path| Project::load(&path))
+        .is_some_and(|project| project.load_sounds());
+
+    set_status(
+        if status { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// -------------------------------------------------------------
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sequencer_load(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint
+) {
+
+    set_status(ProcessStatus::InProgress, CommandType::SequencerLoad);
+
+    let all_notes: Vec<Vec<String>> = match c_song_to_vec(data, sizes_array, outer_size) {
+        Some(n) => n,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    // instruments and samples are resolved now, the sequencer keeps them with the notes
+    let sounds: Option<SoundBank> = SoundBank::from_globals();
+    let song: Option<Vec<Vec<Vec<NoteEvent>>>> = sounds.and_then(|sounds| {
+        all_notes
+            .iter()
+            .map(|rows| rows.iter().map(|row| parse_row(row, &sounds)).collect())
+            .collect()
+    });
+
+    match song {
+        Some(s) => crate::audio_control::sequencer_load(s),
+        None => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sequencer_play() {
+    crate::audio_control::sequencer_transport(|s| s.play());
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sequencer_stop() {
+    crate::audio_control::sequencer_transport(|s| s.stop());
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sequencer_seek(c_milliseconds_position: c_uint) {
+    crate::audio_control::sequencer_transport(|s| s.seek(c_milliseconds_position));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sequencer_set_loop(
+    c_enabled: c_uchar,
+    c_start_milliseconds: c_uint,
+    c_end_milliseconds: c_uint
+) {
+    crate::audio_control::sequencer_transport(|s| {
+        s.set_loop(c_enabled == 1, c_start_milliseconds, c_end_milliseconds)
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sequencer_get_position() -> c_uint {
+    crate::audio_control::sequencer_position()
+}
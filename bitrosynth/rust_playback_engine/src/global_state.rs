@@ -1,10 +1,15 @@
 use std::sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, AtomicI32}};
 use cpal::Stream;
 
+use crate::live_voices::VoiceBank;
+use crate::sequencer::Sequencer;
+
 pub static STREAM: OnceLock<Arc<Mutex<Option<Stream>>>> = OnceLock::new();
 pub static AUDIO_BUFFER: OnceLock<Arc<Mutex<Vec<f32>>>> = OnceLock::new();
 pub static AUDIO_POSITION: OnceLock<Arc<Mutex<usize>>> = OnceLock::new();
 pub static PLAYBACK_STATUS: AtomicBool = AtomicBool::new(false);
+pub static VOICE_BANK: OnceLock<Arc<Mutex<VoiceBank>>> = OnceLock::new();
+pub static SEQUENCER: OnceLock<Arc<Mutex<Sequencer>>> = OnceLock::new();
 
 
 pub static CURRENT_STATUS : AtomicI32 = AtomicI32::new(0);
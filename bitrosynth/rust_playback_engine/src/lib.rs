@@ -2,6 +2,8 @@ pub mod common_types;
 pub mod global_state;
 pub mod audio_stream;
 pub mod audio_control;
+pub mod live_voices;
+pub mod sequencer;
 pub mod wav_reader;
 pub mod utils;
 pub mod ffi;
\ No newline at end of file
@@ -0,0 +1,81 @@
+use rust_synthesize_engine::common_types::Waveform;
+use rust_synthesize_engine::synth::voice::Voice;
+
+
+const MAX_VOICES: usize = 16;
+const ATTACK_SECONDS: f32 = 0.005;
+const RELEASE_SECONDS: f32 = 0.05;
+
+struct LiveVoice {
+    note: String,
+    voice: Voice,
+    level: f32,
+    releasing: bool,
+}
+
+pub struct VoiceBank {
+    voices: Vec<LiveVoice>,
+}
+
+impl VoiceBank {
+    pub fn new() -> VoiceBank {
+        VoiceBank { voices: Vec::with_capacity(MAX_VOICES) }
+    }
+
+    pub fn note_on(&mut self, note: String, waveform: Waveform, frequency: f32, gain: f32) {
+        // retriggering a held key restarts it instead of stacking a second voice
+        self.voices.retain(|v| v.note != note);
+
+        if self.voices.len() >= MAX_VOICES {
+            self.voices.remove(0);
+        }
+
+        self.voices.push(LiveVoice {
+            note,
+            voice: Voice::new(waveform, frequency, gain),
+            level: 0.0,
+            releasing: false,
+        });
+    }
+
+    pub fn note_off(&mut self, note: &str) {
+        for v in self.voices.iter_mut().filter(|v| v.note == note) {
+            v.releasing = true;
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        for v in self.voices.iter_mut() {
+            v.releasing = true;
+        }
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
+        let attack_step: f32 = 1.0 / (ATTACK_SECONDS * sample_rate);
+        let release_step: f32 = 1.0 / (RELEASE_SECONDS * sample_rate);
+        let mut sum: f32 = 0.0;
+
+        for v in self.voices.iter_mut() {
+            if v.releasing {
+                v.level = (v.level - release_step).max(0.0);
+            } else {
+                v.level = (v.level + attack_step).min(1.0);
+            }
+            sum += v.voice.next_sample(sample_rate) * v.level;
+        }
+
+        self.voices.retain(|v| !(v.releasing && v.level <= 0.0));
+
+        sum.clamp(-1.0, 1.0)
+    }
+}
+
+impl Default for VoiceBank {
+    fn default() -> VoiceBank {
+        VoiceBank::new()
+    }
+}
@@ -0,0 +1,253 @@
+use rust_synthesize_engine::common_types::Waveform;
+use rust_synthesize_engine::synth::channel::NoteEvent;
+use rust_synthesize_engine::synth::voice::Voice;
+use rust_synthesize_engine::utils::milliseconds_to_samples;
+
+
+struct ScheduledNote {
+    start: usize,
+    end: usize,
+    event: NoteEvent,
+}
+
+struct SequencedRow {
+    notes: Vec<ScheduledNote>,
+    cursor: usize,
+    voice: Option<Voice>,
+}
+
+impl SequencedRow {
+    fn schedule(events: &[NoteEvent], sample_rate: f32) -> SequencedRow {
+        let mut start: usize = 0;
+        let notes: Vec<ScheduledNote> = events
+            .iter()
+            .map(|event| {
+                // same per-note rounding as the offline renderer, so both timelines line up
+                let end: usize = start + milliseconds_to_samples(event.milliseconds, sample_rate);
+                let note = ScheduledNote { start, end, event: event.clone() };
+                start = end;
+                note
+            })
+            .collect();
+
+        SequencedRow { notes, cursor: 0, voice: None }
+    }
+
+    fn length(&self) -> usize {
+        self.notes.last().map_or(0, |n| n.end)
+    }
+
+    fn seek(&mut self, position: usize) {
+        self.cursor = self.notes.partition_point(|n| n.end <= position);
+        self.voice = None;
+    }
+
+    fn next_sample(&mut self, position: usize, sample_rate: f32) -> f32 {
+        while self.cursor < self.notes.len() && self.notes[self.cursor].end <= position {
+            self.cursor += 1;
+
+            // tied sample notes keep their voice, like the offline renderer keeps the sample position
+            match (self.notes.get(self.cursor), self.voice.as_mut()) {
+                (Some(next), Some(voice))
+                    if next.event.tie
+                        && matches!(next.event.waveform, Waveform::Sample(_))
+                        && voice.waveform() == next.event.waveform =>
+                {
+                    voice.retune(next.event.frequency, next.event.gain);
+                }
+                _ => self.voice = None,
+            }
+        }
+
+        let note: &ScheduledNote = match self.notes.get(self.cursor) {
+            Some(n) if n.start <= position => n,
+            _ => return 0.0,
+        };
+
+        // a fresh voice per note restarts the phase exactly like the offline oscillators
+        let voice: &mut Voice = self.voice.get_or_insert_with(|| Voice::for_note(&note.event));
+        let sample: f32 = voice.next_sample(sample_rate);
+
+        // instrument effects need the whole note and are only heard in offline renders
+        match &note.event.instrument {
+            Some(instrument) if !instrument.envelope.is_flat() => {
+                sample * instrument.envelope.gain_at(position - note.start, note.end - note.start, sample_rate)
+            }
+            _ => sample,
+        }
+    }
+}
+
+
+pub struct Sequencer {
+    song: Vec<Vec<Vec<NoteEvent>>>,
+    pending_song: Option<Vec<Vec<Vec<NoteEvent>>>>,
+    channels: Vec<Vec<SequencedRow>>,
+    scheduled_rate: f32,
+    song_length: usize,
+    position: usize,
+    playing: bool,
+    loop_enabled: bool,
+    loop_start_milliseconds: u32,
+    loop_end_milliseconds: u32,
+}
+
+impl Sequencer {
+    pub fn new() -> Sequencer {
+        Sequencer {
+            song: Vec::new(),
+            pending_song: None,
+            channels: Vec::new(),
+            scheduled_rate: 0.0,
+            song_length: 0,
+            position: 0,
+            playing: false,
+            loop_enabled: false,
+            loop_start_milliseconds: 0,
+            loop_end_milliseconds: 0,
+        }
+    }
+
+    fn reschedule(&mut self, sample_rate: f32) {
+        if self.scheduled_rate > 0.0 && self.scheduled_rate != sample_rate {
+            self.position = (self.position as f64 * sample_rate as f64 / self.scheduled_rate as f64) as usize;
+        }
+        self.scheduled_rate = sample_rate;
+
+        self.channels = self
+            .song
+            .iter()
+            .map(|rows| rows.iter().map(|events| SequencedRow::schedule(events, sample_rate)).collect())
+            .collect();
+
+        self.song_length = self
+            .channels
+            .iter()
+            .flat_map(|rows| rows.iter().map(|r| r.length()))
+            .max()
+            .unwrap_or(0);
+
+        self.seek_samples(self.position);
+    }
+
+    fn seek_samples(&mut self, position: usize) {
+        self.position = position;
+        for row in self.channels.iter_mut().flatten() {
+            row.seek(position);
+        }
+    }
+
+    fn loop_range(&self) -> (usize, usize) {
+        let start: usize = milliseconds_to_samples(self.loop_start_milliseconds, self.scheduled_rate);
+        let end: usize = if self.loop_end_milliseconds == 0 {
+            self.song_length
+        } else {
+            milliseconds_to_samples(self.loop_end_milliseconds, self.scheduled_rate).min(self.song_length)
+        };
+        (start.min(end), end)
+    }
+
+    pub fn load(&mut self, song: Vec<Vec<Vec<NoteEvent>>>) {
+        // while looping, edits wait for the loop boundary so the current pass plays out cleanly
+        if self.playing && self.loop_enabled {
+            self.pending_song = Some(song);
+            return;
+        }
+
+        self.song = song;
+        self.pending_song = None;
+        if self.scheduled_rate > 0.0 {
+            self.reschedule(self.scheduled_rate);
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+        if let Some(song) = self.pending_song.take() {
+            self.song = song;
+        }
+        self.position = 0;
+        if self.scheduled_rate > 0.0 {
+            self.reschedule(self.scheduled_rate);
+        }
+    }
+
+    pub fn seek(&mut self, milliseconds: u32) {
+        if self.scheduled_rate > 0.0 {
+            self.seek_samples(milliseconds_to_samples(milliseconds, self.scheduled_rate));
+        }
+    }
+
+    pub fn set_loop(&mut self, enabled: bool, start_milliseconds: u32, end_milliseconds: u32) {
+        self.loop_enabled = enabled;
+        self.loop_start_milliseconds = start_milliseconds;
+        self.loop_end_milliseconds = end_milliseconds;
+    }
+
+    pub fn position_milliseconds(&self) -> u32 {
+        if self.scheduled_rate <= 0.0 {
+            return 0;
+        }
+        (self.position as f64 * 1000.0 / self.scheduled_rate as f64) as u32
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
+        if self.scheduled_rate != sample_rate {
+            self.reschedule(sample_rate);
+        }
+
+        if self.loop_enabled {
+            let (loop_start, loop_end) = self.loop_range();
+            if loop_end > loop_start && self.position >= loop_end {
+                if let Some(song) = self.pending_song.take() {
+                    self.song = song;
+                    self.reschedule(sample_rate);
+                }
+                self.seek_samples(loop_start);
+            }
+        } else if self.position >= self.song_length {
+            self.playing = false;
+            self.seek_samples(0);
+            return 0.0;
+        }
+
+        let mut sum: f32 = 0.0;
+        let mut active_channels: u32 = 0;
+
+        for rows in self.channels.iter_mut() {
+            let mut channel_sample: f32 = 0.0;
+            for row in rows.iter_mut() {
+                channel_sample += row.next_sample(self.position, sample_rate);
+            }
+
+            // offline rendering peak-normalises whole channels, live playback can only clamp
+            let channel_sample: f32 = channel_sample.clamp(-1.0, 1.0);
+            if channel_sample != 0.0 {
+                sum += channel_sample;
+                active_channels += 1;
+            }
+        }
+
+        self.position += 1;
+
+        if active_channels > 0 {
+            sum / active_channels as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for Sequencer {
+    fn default() -> Sequencer {
+        Sequencer::new()
+    }
+}
@@ -5,6 +5,7 @@ use crate::global_state::{
     CURRENT_STATUS
 };
 use crate::common_types::*;
+use rust_synthesize_engine::jobs::{finish_job, start_job};
 
 pub fn set_status(
     status: ProcessStatus,
@@ -12,4 +13,16 @@ pub fn set_status(
 ){
     CURRENT_STATUS.store(status as i32, Ordering::SeqCst);
     CURRENT_COMMAND.store(current_command as i32,Ordering::SeqCst);
-}
\ No newline at end of file
+}
+
+// registers the job and keeps the legacy status pair in sync for older callers
+pub fn begin_job(command: CommandType) -> u32 {
+    let (job_id, _) = start_job(command as i32);
+    set_status(ProcessStatus::InProgress, command);
+    job_id
+}
+
+pub fn end_job(job_id: u32, status: ProcessStatus, error: Option<&str>) {
+    finish_job(job_id, status as i32, error);
+    set_status(status, CommandType::None);
+}
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::Write;
+
+use crate::audio::dither::Quantizer;
+use crate::audio::raw::push_pcm_sample;
+use crate::common_types::{DitherMode, ExportFormat};
+use crate::progress::{PROGRESS_BLOCK, Progress};
+use crate::render_config::RenderConfig;
+
+
+// AIFF-C version 1 timestamp, the only value defined by the specification
+const AIFC_VERSION_1: u32 = 0xA280_5140;
+
+fn extended_float(value: u32) -> [u8; 10] {
+    let mut output: [u8; 10] = [0; 10];
+    if value == 0 {
+        return output;
+    }
+
+    let shift: u32 = (value as u64).leading_zeros();
+    let mantissa: u64 = (value as u64) << shift;
+    let exponent: u16 = 16383 + 63 - shift as u16;
+
+    output[..2].copy_from_slice(&exponent.to_be_bytes());
+    output[2..].copy_from_slice(&mantissa.to_be_bytes());
+    output
+}
+
+fn push_chunk(output: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    output.extend_from_slice(id);
+    output.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    output.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+fn push_pascal_string(output: &mut Vec<u8>, text: &str) {
+    output.push(text.len() as u8);
+    output.extend_from_slice(text.as_bytes());
+    if text.len().is_multiple_of(2) {
+        output.push(0);
+    }
+}
+
+
+pub fn write_aiff(
+    path: String,
+    samples: Vec<f32>,
+    compressed_header: bool,
+    config: &RenderConfig,
+    progress: &Progress
+) -> bool {
+
+    let export_format: ExportFormat = config.export_format;
+    let dither_mode: DitherMode = config.dither_mode;
+    let sample_rate: u32 = config.sample_rate;
+    let bits_per_sample: u16 = export_format.bits_per_sample();
+
+    // plain AIFF cannot describe float samples, those always go out as AIFF-C
+    let compressed_header: bool = compressed_header || export_format.is_float();
+
+    let mut common: Vec<u8> = Vec::new();
+    common.extend_from_slice(&1u16.to_be_bytes());
+    common.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+    common.extend_from_slice(&bits_per_sample.to_be_bytes());
+    common.extend_from_slice(&extended_float(sample_rate));
+    if compressed_header {
+        if export_format.is_float() {
+            common.extend_from_slice(b"fl32");
+            push_pascal_string(&mut common, "32-bit floating point");
+        } else {
+            common.extend_from_slice(b"NONE");
+            push_pascal_string(&mut common, "not compressed");
+        }
+    }
+
+    let mut sound_data: Vec<u8> = Vec::with_capacity(8 + samples.len() * (bits_per_sample / 8) as usize);
+    sound_data.extend_from_slice(&0u32.to_be_bytes());
+    sound_data.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut quantizer: Quantizer = Quantizer::new(dither_mode, bits_per_sample);
+
+    for block in samples.chunks(PROGRESS_BLOCK) {
+        for &s in block {
+            if export_format.is_float() {
+                sound_data.extend_from_slice(&s.to_be_bytes());
+            } else {
+                push_pcm_sample(&mut sound_data, quantizer.quantize(s), bits_per_sample, true, true);
+            }
+        }
+        if !progress.advance(block.len() as u64) {
+            return false;
+        }
+    }
+
+    let mut body: Vec<u8> = Vec::new();
+    if compressed_header {
+        body.extend_from_slice(b"AIFC");
+        push_chunk(&mut body, b"FVER", &AIFC_VERSION_1.to_be_bytes());
+    } else {
+        body.extend_from_slice(b"AIFF");
+    }
+    push_chunk(&mut body, b"COMM", &common);
+    push_chunk(&mut body, b"SSND", &sound_data);
+
+    let mut output: Vec<u8> = Vec::with_capacity(body.len() + 8);
+    push_chunk(&mut output, b"FORM", &body);
+
+    let mut file = match File::create(path) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+
+    file.write_all(&output).is_ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(export_format: ExportFormat, compressed_header: bool, samples: Vec<f32>) -> Vec<u8> {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.export_format = export_format;
+        config.dither_mode = DitherMode::None;
+        config.sample_rate = 44100;
+
+        let path = std::env::temp_dir().join(format!("bitrosynth_aiff_test_{}.aif", export_format.bits_per_sample()));
+        assert!(write_aiff(path.to_string_lossy().into_owned(), samples, compressed_header, &config, &Progress::new()));
+        let bytes: Vec<u8> = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        bytes
+    }
+
+    #[test]
+    fn sample_rates_are_stored_as_80_bit_floats() {
+        assert_eq!(extended_float(44100), [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended_float(48000), [0x40, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended_float(1), [0x3F, 0xFF, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended_float(0), [0; 10]);
+    }
+
+    #[test]
+    fn writes_a_big_endian_aiff_header() {
+        let bytes: Vec<u8> = write(ExportFormat::Int16, false, vec![0.0, 1.0, -1.0]);
+
+        let mut expected: Vec<u8> = b"FORM".to_vec();
+        expected.extend_from_slice(&52u32.to_be_bytes());
+        expected.extend_from_slice(b"AIFFCOMM");
+        expected.extend_from_slice(&18u32.to_be_bytes());
+        expected.extend_from_slice(&[0, 1, 0, 0, 0, 3, 0, 16]);
+        expected.extend_from_slice(&extended_float(44100));
+        expected.extend_from_slice(b"SSND");
+        expected.extend_from_slice(&14u32.to_be_bytes());
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x00, 0x00, 0x7F, 0xFF, 0x80, 0x01]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn float_samples_go_out_as_aiff_c() {
+        let bytes: Vec<u8> = write(ExportFormat::Float32, false, vec![0.5]);
+
+        assert_eq!(&bytes[8..12], b"AIFC");
+        assert_eq!(&bytes[12..20], b"FVER\0\0\0\x04");
+        assert_eq!(&bytes[20..24], &AIFC_VERSION_1.to_be_bytes());
+        assert_eq!(&bytes[24..28], b"COMM");
+        assert_eq!(&bytes[28..32], &44u32.to_be_bytes());
+        assert_eq!(&bytes[38..40], &32u16.to_be_bytes());
+        assert_eq!(&bytes[50..54], b"fl32");
+        assert_eq!(bytes[54], 21);
+        assert_eq!(&bytes[55..76], b"32-bit floating point");
+        assert_eq!(&bytes[76..80], b"SSND");
+        assert_eq!(&bytes[92..], &0.5f32.to_be_bytes());
+        assert_eq!(u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as usize, bytes.len() - 8);
+    }
+
+    #[test]
+    fn uncompressed_aiff_c_pads_its_compression_name() {
+        let bytes: Vec<u8> = write(ExportFormat::Int8, true, vec![1.0]);
+
+        assert_eq!(&bytes[28..32], &38u32.to_be_bytes());
+        assert_eq!(&bytes[50..54], b"NONE");
+        assert_eq!(bytes[54], 14);
+        assert_eq!(&bytes[55..70], b"not compressed\0");
+        assert_eq!(&bytes[70..74], b"SSND");
+        assert_eq!(&bytes[74..78], &9u32.to_be_bytes());
+        // odd sized sound data gets a pad byte after the chunk
+        assert_eq!(&bytes[86..], &[0x7F, 0]);
+    }
+}
@@ -0,0 +1,114 @@
+use rand::prelude::*;
+
+use crate::common_types::DitherMode;
+
+
+// error feedback filter E(z) * (1 - z^-1)^2, pushes quantization noise towards Nyquist
+const NOISE_SHAPING_COEFFICIENTS: [f32; 2] = [2.0, -1.0];
+
+pub struct Quantizer {
+    mode: DitherMode,
+    scale: f32,
+    min_value: f32,
+    max_value: f32,
+    error_history: [f32; 2],
+    rng: ThreadRng,
+}
+
+impl Quantizer {
+    pub fn new(mode: DitherMode, bits_per_sample: u16) -> Quantizer {
+        let max_value: f32 = ((1i64 << (bits_per_sample - 1)) - 1) as f32;
+
+        Quantizer {
+            mode,
+            scale: max_value,
+            min_value: -max_value - 1.0,
+            max_value,
+            error_history: [0.0; 2],
+            rng: rand::rng(),
+        }
+    }
+
+    fn tpdf(&mut self) -> f32 {
+        // sum of two uniform values gives a triangular distribution spanning +-1 LSB
+        self.rng.random_range(-0.5f32..0.5) + self.rng.random_range(-0.5f32..0.5)
+    }
+
+    pub fn quantize(&mut self, sample: f32) -> i32 {
+        let target: f32 = sample.clamp(-1.0, 1.0) * self.scale;
+
+        let quantized: f32 = match self.mode {
+            DitherMode::None => target.round(),
+            DitherMode::Tpdf => (target + self.tpdf()).round(),
+            DitherMode::TpdfNoiseShaped => {
+                let shaped: f32 = target
+                    - NOISE_SHAPING_COEFFICIENTS[0] * self.error_history[0]
+                    - NOISE_SHAPING_COEFFICIENTS[1] * self.error_history[1];
+                let quantized: f32 = (shaped + self.tpdf()).round();
+
+                // limit the fed back error so clipped peaks cannot destabilise the loop
+                let error: f32 = (quantized - shaped).clamp(-1.5, 1.5);
+                self.error_history[1] = self.error_history[0];
+                self.error_history[0] = error;
+
+                quantized
+            }
+        };
+
+        quantized.clamp(self.min_value, self.max_value) as i32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(length: usize) -> Vec<f32> {
+        (0..length).map(|n| 0.5 * (n as f32 * 0.01).sin()).collect()
+    }
+
+    #[test]
+    fn without_dither_samples_round_to_the_nearest_step() {
+        let mut quantizer: Quantizer = Quantizer::new(DitherMode::None, 8);
+        assert_eq!(quantizer.quantize(0.0), 0);
+        assert_eq!(quantizer.quantize(10.4 / 127.0), 10);
+        assert_eq!(quantizer.quantize(10.6 / 127.0), 11);
+        assert_eq!(quantizer.quantize(-10.6 / 127.0), -11);
+        assert_eq!(quantizer.quantize(1.0), 127);
+        assert_eq!(quantizer.quantize(-1.0), -127);
+        assert_eq!(quantizer.quantize(4.0), 127);
+
+        let mut quantizer: Quantizer = Quantizer::new(DitherMode::None, 16);
+        assert_eq!(quantizer.quantize(1.0), 32767);
+        assert_eq!(quantizer.quantize(-0.5), -16384);
+    }
+
+    #[test]
+    fn tpdf_dither_stays_within_one_step_and_is_unbiased() {
+        let mut quantizer: Quantizer = Quantizer::new(DitherMode::Tpdf, 8);
+        let target: f32 = 10.3;
+
+        let outputs: Vec<i32> = (0..20000).map(|_| quantizer.quantize(target / 127.0)).collect();
+        assert!(outputs.iter().all(|&q| (q as f32 - target).abs() <= 1.5));
+
+        let mean: f32 = outputs.iter().sum::<i32>() as f32 / outputs.len() as f32;
+        assert!((mean - target).abs() < 0.05, "{}", mean);
+    }
+
+    #[test]
+    fn noise_shaping_keeps_the_error_bounded_and_away_from_dc() {
+        let mut quantizer: Quantizer = Quantizer::new(DitherMode::TpdfNoiseShaped, 16);
+        let mut error_sum: f32 = 0.0;
+
+        for sample in sine(20000) {
+            let error: f32 = quantizer.quantize(sample) as f32 - sample * 32767.0;
+            // the loop adds e[n] - 2e[n-1] + e[n-2] with every |e| <= 1.5 steps
+            assert!(error.abs() <= 6.0, "{}", error);
+
+            // so the running sum telescopes to e[n] - e[n-1] instead of drifting
+            error_sum += error;
+            assert!(error_sum.abs() <= 3.1, "{}", error_sum);
+        }
+    }
+}
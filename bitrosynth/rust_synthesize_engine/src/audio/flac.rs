@@ -0,0 +1,564 @@
+use std::fs::File;
+use std::io::Write;
+
+use crate::audio::dither::Quantizer;
+use crate::common_types::{DitherMode, ExportFormat};
+use crate::progress::Progress;
+use crate::render_config::RenderConfig;
+
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_LPC_ORDER: usize = 8;
+const LPC_PRECISION: u32 = 12;
+const MAX_PARTITION_ORDER: u32 = 8;
+const MAX_BITS_PER_SAMPLE: u16 = 24;
+
+
+// ------------------------------------------------------------------------------
+// Bit level helpers
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: Vec::new(), accumulator: 0, bit_count: 0 }
+    }
+
+    fn write_bits(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        if bits > 32 {
+            self.write_bits(value >> 32, bits - 32);
+            self.write_bits(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+
+        let mask: u64 = (1u64 << bits) - 1;
+        self.accumulator = (self.accumulator << bits) | (value & mask);
+        self.bit_count += bits;
+
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            self.bytes.push((self.accumulator >> self.bit_count) as u8);
+        }
+        self.accumulator &= (1u64 << self.bit_count) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write_bits(value as u64, bits);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        let mut remaining: u64 = zeros;
+        while remaining >= 32 {
+            self.write_bits(0, 32);
+            remaining -= 32;
+        }
+        self.write_bits(1, remaining as u32 + 1);
+    }
+
+    fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.write_bits(0, 8 - self.bit_count);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn write_utf8_number(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write_bits(value, 8);
+        return;
+    }
+
+    let continuation_bytes: u32 = match value {
+        0..0x800 => 1,
+        0x800..0x10000 => 2,
+        0x10000..0x200000 => 3,
+        0x200000..0x4000000 => 4,
+        _ => 5,
+    };
+
+    let lead_marker: u64 = (0xFF00u64 >> (continuation_bytes + 1)) & 0xFF;
+    writer.write_bits(lead_marker | (value >> (6 * continuation_bytes)), 8);
+    for i in (0..continuation_bytes).rev() {
+        writer.write_bits(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+
+// ------------------------------------------------------------------------------
+// Prediction
+
+enum Predictor {
+    Fixed(usize),
+    Lpc { coefficients: Vec<i64>, shift: u32 },
+}
+
+impl Predictor {
+    fn order(&self) -> usize {
+        match self {
+            Predictor::Fixed(order) => *order,
+            Predictor::Lpc { coefficients, .. } => coefficients.len(),
+        }
+    }
+}
+
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| match order {
+            0 => samples[i],
+            1 => samples[i] - samples[i - 1],
+            2 => samples[i] - 2 * samples[i - 1] + samples[i - 2],
+            3 => samples[i] - 3 * samples[i - 1] + 3 * samples[i - 2] - samples[i - 3],
+            _ => samples[i] - 4 * samples[i - 1] + 6 * samples[i - 2] - 4 * samples[i - 3] + samples[i - 4],
+        })
+        .collect()
+}
+
+fn lpc_residual(samples: &[i64], coefficients: &[i64], shift: u32) -> Vec<i64> {
+    let order: usize = coefficients.len();
+
+    (order..samples.len())
+        .map(|i| {
+            let prediction: i64 = coefficients
+                .iter()
+                .enumerate()
+                .map(|(j, c)| c * samples[i - 1 - j])
+                .sum();
+            samples[i] - (prediction >> shift)
+        })
+        .collect()
+}
+
+// Levinson-Durbin on a Welch windowed autocorrelation, returns coefficients for every order
+fn lpc_candidates(samples: &[i64], max_order: usize) -> Vec<Vec<f64>> {
+    let length: usize = samples.len();
+    let half: f64 = (length as f64 - 1.0) / 2.0;
+    let windowed: Vec<f64> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| {
+            let x: f64 = (i as f64 - half) / (half + 1.0);
+            s as f64 * (1.0 - x * x)
+        })
+        .collect();
+
+    let autocorrelation: Vec<f64> = (0..=max_order)
+        .map(|lag| (lag..length).map(|i| windowed[i] * windowed[i - lag]).sum())
+        .collect();
+
+    if autocorrelation[0] <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<Vec<f64>> = Vec::with_capacity(max_order);
+    let mut coefficients: Vec<f64> = Vec::new();
+    let mut error: f64 = autocorrelation[0];
+
+    for order in 1..=max_order {
+        let mut reflection: f64 = autocorrelation[order];
+        for (j, c) in coefficients.iter().enumerate() {
+            reflection -= c * autocorrelation[order - 1 - j];
+        }
+        reflection /= error;
+
+        let previous: Vec<f64> = coefficients.clone();
+        coefficients.push(reflection);
+        for j in 0..order - 1 {
+            coefficients[j] = previous[j] - reflection * previous[order - 2 - j];
+        }
+
+        error *= 1.0 - reflection * reflection;
+        candidates.push(coefficients.clone());
+
+        if error <= 0.0 {
+            break;
+        }
+    }
+
+    candidates
+}
+
+fn quantize_lpc(coefficients: &[f64]) -> Option<(Vec<i64>, u32)> {
+    let max_coefficient: f64 = coefficients.iter().fold(0.0f64, |a, b| a.max(b.abs()));
+    if max_coefficient <= 0.0 || !max_coefficient.is_finite() {
+        return None;
+    }
+
+    let limit: i64 = (1 << (LPC_PRECISION - 1)) - 1;
+    let exponent: i32 = max_coefficient.log2().floor() as i32 + 1;
+    let shift: i32 = (LPC_PRECISION as i32 - 1 - exponent).min(15);
+    if shift < 0 {
+        return None;
+    }
+
+    // carry the rounding error forward so the quantized filter keeps its response
+    let mut error: f64 = 0.0;
+    let quantized: Vec<i64> = coefficients
+        .iter()
+        .map(|c| {
+            error += c * (1i64 << shift) as f64;
+            let q: i64 = (error.round() as i64).clamp(-limit - 1, limit);
+            error -= q as f64;
+            q
+        })
+        .collect();
+
+    Some((quantized, shift as u32))
+}
+
+
+// ------------------------------------------------------------------------------
+// Residual coding
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn rice_parameter(sum: u64, count: usize, max_parameter: u32) -> u32 {
+    if count == 0 || sum < count as u64 {
+        return 0;
+    }
+    let mean: u64 = sum / count as u64;
+    (63 - mean.leading_zeros()).min(max_parameter)
+}
+
+fn estimate_bits(residual: &[i64], max_parameter: u32) -> u64 {
+    let sum: u64 = residual.iter().map(|&r| zigzag(r)).sum();
+    let parameter: u32 = rice_parameter(sum, residual.len(), max_parameter);
+    residual.len() as u64 * (parameter as u64 + 1) + (sum >> parameter)
+}
+
+struct RicePlan {
+    partition_order: u32,
+    parameters: Vec<u32>,
+}
+
+fn plan_partitions(residual: &[i64], order: usize, block_size: usize, max_parameter: u32, parameter_bits: u32) -> RicePlan {
+    let encoded: Vec<u64> = residual.iter().map(|&r| zigzag(r)).collect();
+    let mut best: Option<(u64, RicePlan)> = None;
+
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions: usize = 1 << partition_order;
+        if !block_size.is_multiple_of(partitions) || block_size / partitions <= order {
+            break;
+        }
+
+        let partition_size: usize = block_size / partitions;
+        let mut parameters: Vec<u32> = Vec::with_capacity(partitions);
+        let mut bits: u64 = 0;
+        let mut start: usize = 0;
+
+        for partition in 0..partitions {
+            let count: usize = if partition == 0 { partition_size - order } else { partition_size };
+            let values: &[u64] = &encoded[start..start + count];
+            let sum: u64 = values.iter().sum();
+            let parameter: u32 = rice_parameter(sum, count, max_parameter);
+
+            bits += parameter_bits as u64
+                + count as u64 * (parameter as u64 + 1)
+                + values.iter().map(|u| u >> parameter).sum::<u64>();
+            parameters.push(parameter);
+            start += count;
+        }
+
+        if best.as_ref().is_none_or(|(best_bits, _)| bits < *best_bits) {
+            best = Some((bits, RicePlan { partition_order, parameters }));
+        }
+    }
+
+    match best {
+        Some((_, plan)) => plan,
+        None => RicePlan { partition_order: 0, parameters: vec![rice_parameter(encoded.iter().sum(), encoded.len(), max_parameter)] },
+    }
+}
+
+fn write_residual(writer: &mut BitWriter, residual: &[i64], order: usize, block_size: usize, bits_per_sample: u32) {
+    // 24-bit material can need parameters above 14, which only the 5-bit method can hold
+    let (method, parameter_bits, max_parameter): (u64, u32, u32) = if bits_per_sample > 16 {
+        (1, 5, 30)
+    } else {
+        (0, 4, 14)
+    };
+
+    let plan: RicePlan = plan_partitions(residual, order, block_size, max_parameter, parameter_bits);
+    writer.write_bits(method, 2);
+    writer.write_bits(plan.partition_order as u64, 4);
+
+    let partition_size: usize = block_size >> plan.partition_order;
+    let mut start: usize = 0;
+
+    for (partition, &parameter) in plan.parameters.iter().enumerate() {
+        let count: usize = if partition == 0 { partition_size - order } else { partition_size };
+        writer.write_bits(parameter as u64, parameter_bits);
+
+        for &r in &residual[start..start + count] {
+            let value: u64 = zigzag(r);
+            writer.write_unary(value >> parameter);
+            writer.write_bits(value, parameter);
+        }
+        start += count;
+    }
+}
+
+
+// ------------------------------------------------------------------------------
+// Frames
+
+fn write_subframe(writer: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    let block_size: usize = samples.len();
+
+    if samples.iter().all(|&s| s == samples[0]) {
+        writer.write_bits(0, 8);
+        writer.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    let max_parameter: u32 = if bits_per_sample > 16 { 30 } else { 14 };
+    let max_order: usize = block_size.saturating_sub(1);
+
+    let mut best: Option<(u64, Predictor, Vec<i64>)> = None;
+    let mut consider = |predictor: Predictor, residual: Vec<i64>, header_bits: u64| {
+        let bits: u64 = header_bits + estimate_bits(&residual, max_parameter);
+        if best.as_ref().is_none_or(|(best_bits, _, _)| bits < *best_bits) {
+            best = Some((bits, predictor, residual));
+        }
+    };
+
+    for order in 0..=MAX_FIXED_ORDER.min(max_order) {
+        consider(Predictor::Fixed(order), fixed_residual(samples, order), order as u64 * bits_per_sample as u64);
+    }
+
+    for candidate in lpc_candidates(samples, MAX_LPC_ORDER.min(max_order)) {
+        if let Some((coefficients, shift)) = quantize_lpc(&candidate) {
+            let order: u64 = coefficients.len() as u64;
+            let residual: Vec<i64> = lpc_residual(samples, &coefficients, shift);
+            consider(
+                Predictor::Lpc { coefficients, shift },
+                residual,
+                order * (bits_per_sample + LPC_PRECISION) as u64 + 9,
+            );
+        }
+    }
+
+    let (bits, predictor, residual) = match best {
+        Some(b) => b,
+        None => return,
+    };
+
+    if bits >= (block_size as u64) * bits_per_sample as u64 {
+        writer.write_bits(0b0000_0010, 8);
+        for &s in samples {
+            writer.write_signed(s, bits_per_sample);
+        }
+        return;
+    }
+
+    let order: usize = predictor.order();
+    match &predictor {
+        Predictor::Fixed(_) => writer.write_bits((0b00_1000 | order as u64) << 1, 8),
+        Predictor::Lpc { .. } => writer.write_bits((0b10_0000 | (order as u64 - 1)) << 1, 8),
+    }
+
+    for &s in &samples[..order] {
+        writer.write_signed(s, bits_per_sample);
+    }
+
+    if let Predictor::Lpc { coefficients, shift } = &predictor {
+        writer.write_bits(LPC_PRECISION as u64 - 1, 4);
+        writer.write_signed(*shift as i64, 5);
+        for &c in coefficients {
+            writer.write_signed(c, LPC_PRECISION);
+        }
+    }
+
+    write_residual(writer, &residual, order, block_size, bits_per_sample);
+}
+
+fn encode_frame(samples: &[i64], frame_number: u64, bits_per_sample: u32) -> Vec<u8> {
+    let mut writer: BitWriter = BitWriter::new();
+
+    writer.write_bits(0b11_1111_1111_1110, 14);
+    writer.write_bits(0, 1);
+    writer.write_bits(0, 1);
+    writer.write_bits(0b0111, 4);
+    writer.write_bits(0b0000, 4);
+    writer.write_bits(0b0000, 4);
+    writer.write_bits(match bits_per_sample { 8 => 0b001, 16 => 0b100, _ => 0b110 }, 3);
+    writer.write_bits(0, 1);
+    write_utf8_number(&mut writer, frame_number);
+    writer.write_bits(samples.len() as u64 - 1, 16);
+
+    let header_crc: u8 = crc8(&writer.bytes);
+    writer.write_bits(header_crc as u64, 8);
+
+    write_subframe(&mut writer, samples, bits_per_sample);
+    writer.align();
+
+    let frame_crc: u16 = crc16(&writer.bytes);
+    writer.write_bits(frame_crc as u64, 16);
+
+    writer.bytes
+}
+
+fn stream_info(sample_rate: u32, bits_per_sample: u32, total_samples: u64) -> Vec<u8> {
+    let mut writer: BitWriter = BitWriter::new();
+
+    writer.write_bits(BLOCK_SIZE as u64, 16);
+    writer.write_bits(BLOCK_SIZE as u64, 16);
+    writer.write_bits(0, 24);
+    writer.write_bits(0, 24);
+    writer.write_bits(sample_rate as u64, 20);
+    writer.write_bits(0, 3);
+    writer.write_bits(bits_per_sample as u64 - 1, 5);
+    writer.write_bits(total_samples, 36);
+    // MD5 signature left empty, which the format defines as "not computed"
+    writer.write_bits(0, 64);
+    writer.write_bits(0, 64);
+
+    writer.bytes
+}
+
+
+// FLAC has no float samples and this encoder stops at 24 bits
+pub fn supports_format(export_format: ExportFormat) -> bool {
+    !export_format.is_float() && export_format.bits_per_sample() <= MAX_BITS_PER_SAMPLE
+}
+
+pub fn write_flac(
+    path: String,
+    samples: Vec<f32>,
+    config: &RenderConfig,
+    progress: &Progress
+) -> bool {
+
+    let export_format: ExportFormat = config.export_format;
+    let dither_mode: DitherMode = config.dither_mode;
+    let sample_rate: u32 = config.sample_rate;
+
+    if !supports_format(export_format) {
+        return false;
+    }
+    let bits_per_sample: u32 = export_format.bits_per_sample() as u32;
+
+    let mut quantizer: Quantizer = Quantizer::new(dither_mode, bits_per_sample as u16);
+    let quantized: Vec<i64> = samples.iter().map(|&s| quantizer.quantize(s) as i64).collect();
+
+    let mut output: Vec<u8> = Vec::new();
+    output.extend_from_slice(b"fLaC");
+
+    // last metadata block flag + STREAMINFO type, followed by the 34 byte block
+    let info: Vec<u8> = stream_info(sample_rate, bits_per_sample, quantized.len() as u64);
+    output.push(0x80);
+    output.extend_from_slice(&(info.len() as u32).to_be_bytes()[1..]);
+    output.extend_from_slice(&info);
+
+    for (frame_number, block) in quantized.chunks(BLOCK_SIZE).enumerate() {
+        output.extend_from_slice(&encode_frame(block, frame_number as u64, bits_per_sample));
+        if !progress.advance(block.len() as u64) {
+            return false;
+        }
+    }
+
+    let mut file = match File::create(path) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+
+    file.write_all(&output).is_ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a sine, a burst of pseudo random noise, digital silence and clipped full scale
+    fn test_signal() -> Vec<f32> {
+        let mut seed: u32 = 12345;
+        let mut samples: Vec<f32> = (0..6000).map(|n| (n as f32 * 0.031).sin() * 0.8).collect();
+        samples.extend((0..3000).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as f32 / 32768.0 - 1.0
+        }));
+        samples.extend(std::iter::repeat_n(0.0, 2000));
+        samples.extend((0..1500).map(|n| if n % 40 < 20 { 1.5 } else { -1.5 }));
+        samples
+    }
+
+    fn round_trip(export_format: ExportFormat) {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.export_format = export_format;
+        config.dither_mode = DitherMode::None;
+        config.sample_rate = 44100;
+
+        let bits_per_sample: u16 = export_format.bits_per_sample();
+        let path = std::env::temp_dir().join(format!("bitrosynth_flac_test_{}.flac", bits_per_sample));
+        let samples: Vec<f32> = test_signal();
+        assert!(write_flac(path.to_string_lossy().into_owned(), samples.clone(), &config, &Progress::new()));
+
+        let mut reader = claxon::FlacReader::open(&path).unwrap();
+        assert_eq!(reader.streaminfo().bits_per_sample, bits_per_sample as u32);
+        assert_eq!(reader.streaminfo().sample_rate, 44100);
+        let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        let _ = std::fs::remove_file(&path);
+
+        let mut quantizer: Quantizer = Quantizer::new(DitherMode::None, bits_per_sample);
+        let expected: Vec<i32> = samples.iter().map(|&s| quantizer.quantize(s)).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn decodes_bit_exact_at_8_bits() {
+        round_trip(ExportFormat::Int8);
+    }
+
+    #[test]
+    fn decodes_bit_exact_at_16_bits() {
+        round_trip(ExportFormat::Int16);
+    }
+
+    #[test]
+    fn decodes_bit_exact_at_24_bits() {
+        round_trip(ExportFormat::Int24);
+    }
+
+    #[test]
+    fn refuses_formats_flac_cannot_hold() {
+        assert!(!supports_format(ExportFormat::Int32));
+        assert!(!supports_format(ExportFormat::Float32));
+
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.export_format = ExportFormat::Float32;
+        let path = std::env::temp_dir().join("bitrosynth_flac_test_float.flac");
+        assert!(!write_flac(path.to_string_lossy().into_owned(), vec![0.0; 100], &config, &Progress::new()));
+    }
+}
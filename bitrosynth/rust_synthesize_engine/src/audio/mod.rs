@@ -1 +1,53 @@
-pub mod wav;
\ No newline at end of file
+pub mod wav;
+pub mod wav_metadata;
+pub mod dither;
+pub mod flac;
+pub mod aiff;
+pub mod raw;
+
+use crate::common_types::{ContainerFormat, ExportFormat};
+use crate::audio::wav_metadata::CueMarker;
+use crate::progress::Progress;
+use crate::render_config::RenderConfig;
+
+
+// formats the container behind path cannot store, checked before any rendering
+pub fn format_error(path: &str, container_format: ContainerFormat, export_format: ExportFormat) -> Option<&'static str> {
+    match container_format.resolve(path) {
+        ContainerFormat::Flac if !flac::supports_format(export_format) => {
+            Some("FLAC cannot hold 32 bit or float samples")
+        }
+        _ => None,
+    }
+}
+
+pub fn write_audio(
+    path: String,
+    samples: Vec<f32>,
+    boundaries: &[CueMarker],
+    config: &RenderConfig,
+    progress: &Progress
+) -> bool {
+    let container: ContainerFormat = config.container_format.resolve(&path);
+
+    progress.add_work(samples.len() as u64);
+
+    match container {
+        ContainerFormat::Flac => flac::write_flac(path, samples, config, progress),
+        ContainerFormat::Aiff => aiff::write_aiff(path, samples, false, config, progress),
+        ContainerFormat::AiffC => aiff::write_aiff(path, samples, true, config, progress),
+        ContainerFormat::Raw => raw::write_raw(path, samples, config, progress),
+        _ => {
+            let total_samples: u32 = samples.len() as u32;
+
+            wav::write_wav(path.clone(), samples, config, progress)
+                && wav_metadata::append_wav_metadata(
+                    &path,
+                    &config.wav_metadata,
+                    boundaries,
+                    config.sample_rate,
+                    total_samples,
+                )
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::Write;
+
+use crate::audio::dither::Quantizer;
+use crate::common_types::DitherMode;
+use crate::progress::{PROGRESS_BLOCK, Progress};
+use crate::render_config::RenderConfig;
+
+
+pub fn push_pcm_sample(
+    output: &mut Vec<u8>,
+    value: i32,
+    bits_per_sample: u16,
+    big_endian: bool,
+    signed: bool
+) {
+    let bytes_per_sample: usize = (bits_per_sample / 8) as usize;
+
+    // unsigned PCM is the signed value shifted up by half the range
+    let stored: u32 = if signed {
+        value as u32
+    } else {
+        (value as i64 + (1i64 << (bits_per_sample - 1))) as u32
+    };
+
+    let little: [u8; 4] = stored.to_le_bytes();
+    if big_endian {
+        output.extend(little[..bytes_per_sample].iter().rev());
+    } else {
+        output.extend_from_slice(&little[..bytes_per_sample]);
+    }
+}
+
+
+pub fn write_raw(
+    path: String,
+    samples: Vec<f32>,
+    config: &RenderConfig,
+    progress: &Progress
+) -> bool {
+
+    let big_endian: bool = config.raw_big_endian;
+    let signed: bool = config.raw_signed;
+    let bits_per_sample: u16 = config.raw_bits_per_sample;
+    let dither_mode: DitherMode = config.dither_mode;
+
+    let mut quantizer: Quantizer = Quantizer::new(dither_mode, bits_per_sample);
+    let mut output: Vec<u8> = Vec::with_capacity(samples.len() * (bits_per_sample / 8) as usize);
+
+    for block in samples.chunks(PROGRESS_BLOCK) {
+        for &s in block {
+            push_pcm_sample(&mut output, quantizer.quantize(s), bits_per_sample, big_endian, signed);
+        }
+        if !progress.advance(block.len() as u64) {
+            return false;
+        }
+    }
+
+    let mut file = match File::create(path) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+
+    file.write_all(&output).is_ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcm(value: i32, bits_per_sample: u16, big_endian: bool, signed: bool) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        push_pcm_sample(&mut output, value, bits_per_sample, big_endian, signed);
+        output
+    }
+
+    #[test]
+    fn samples_follow_the_requested_layout() {
+        assert_eq!(pcm(32767, 16, true, true), [0x7F, 0xFF]);
+        assert_eq!(pcm(32767, 16, false, true), [0xFF, 0x7F]);
+        assert_eq!(pcm(-8_388_607, 24, false, true), [0x01, 0x00, 0x80]);
+        assert_eq!(pcm(-8_388_607, 24, true, true), [0x80, 0x00, 0x01]);
+        assert_eq!(pcm(-2, 32, true, true), [0xFF, 0xFF, 0xFF, 0xFE]);
+    }
+
+    #[test]
+    fn unsigned_samples_are_offset_by_half_the_range() {
+        assert_eq!(pcm(0, 8, false, false), [0x80]);
+        assert_eq!(pcm(127, 8, false, false), [0xFF]);
+        assert_eq!(pcm(-128, 8, false, false), [0x00]);
+        assert_eq!(pcm(0, 16, true, false), [0x80, 0x00]);
+        assert_eq!(pcm(-32767, 16, false, false), [0x01, 0x00]);
+    }
+
+    #[test]
+    fn raw_files_hold_only_sample_data() {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.dither_mode = DitherMode::None;
+        config.raw_bits_per_sample = 16;
+        config.raw_big_endian = true;
+        config.raw_signed = false;
+
+        let path = std::env::temp_dir().join("bitrosynth_raw_test.raw");
+        assert!(write_raw(path.to_string_lossy().into_owned(), vec![0.0, 1.0, -1.0], &config, &Progress::new()));
+        let bytes: Vec<u8> = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(bytes, [0x80, 0x00, 0xFF, 0xFF, 0x00, 0x01]);
+    }
+}
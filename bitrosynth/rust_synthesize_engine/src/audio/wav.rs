@@ -1,63 +1,62 @@
 use hound;
-use crate::global_state::{BIT_8_STATUS, SAMPLE_RATE};
-use std::sync::atomic::Ordering;
+use crate::audio::dither::Quantizer;
+use crate::common_types::{DitherMode, ExportFormat};
+use crate::progress::{PROGRESS_BLOCK, Progress};
+use crate::render_config::RenderConfig;
 
 
 pub fn write_wav(
     path: String,
-    samples: Vec<f32>
+    samples: Vec<f32>,
+    config: &RenderConfig,
+    progress: &Progress
 ) -> bool {
 
-
-    if BIT_8_STATUS.load(Ordering::SeqCst) == true {
-        // 8 Bit
-        let spec: hound::WavSpec = hound::WavSpec {
-            channels: 1,
-            sample_rate: SAMPLE_RATE.load(Ordering::SeqCst),
-            bits_per_sample: 8,
-            sample_format: hound::SampleFormat::Int,
-        };
-
-        let mut writer = match hound::WavWriter::create(path, spec) {
-            Ok(o) => o,
-            Err(_) => return false,
-        };
-        for s in samples {
-            let val: i8 = (s * i8::MAX as f32) as i8;
-            match writer.write_sample(val) {
-                Ok(_o) => {}
-                Err(_) => return false,
-            };
+    let export_format: ExportFormat = config.export_format;
+    let dither_mode: DitherMode = config.dither_mode;
+
+    let spec: hound::WavSpec = hound::WavSpec {
+        channels: 1,
+        sample_rate: config.sample_rate,
+        bits_per_sample: export_format.bits_per_sample(),
+        sample_format: if export_format.is_float() {
+            hound::SampleFormat::Float
+        } else {
+            hound::SampleFormat::Int
+        },
+    };
+
+    let mut writer = match hound::WavWriter::create(path, spec) {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+
+    if export_format.is_float() {
+        // float stems are written untouched, values above full scale are preserved
+        for block in samples.chunks(PROGRESS_BLOCK) {
+            for &s in block {
+                if writer.write_sample(s).is_err() {
+                    return false;
+                }
+            }
+            if !progress.advance(block.len() as u64) {
+                return false;
+            }
         }
-        match writer.finalize() {
-            Ok(_) => return true,
-            Err(_) => return false,
-        };
     } else {
-        // 16 Bit
-        let spec: hound::WavSpec = hound::WavSpec {
-            channels: 1,
-            sample_rate: SAMPLE_RATE.load(Ordering::SeqCst),
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
-
-        let mut writer = match hound::WavWriter::create(path, spec) {
-            Ok(o) => o,
-            Err(_) => return false,
-        };
-
-        for s in samples {
-            let val = (s * i16::MAX as f32) as i16;
-            match writer.write_sample(val) {
-                Ok(_o) => {}
-                Err(_) => return false,
-            };
+        let mut quantizer: Quantizer = Quantizer::new(dither_mode, export_format.bits_per_sample());
+
+        for block in samples.chunks(PROGRESS_BLOCK) {
+            for &s in block {
+                if writer.write_sample(quantizer.quantize(s)).is_err() {
+                    return false;
+                }
+            }
+            if !progress.advance(block.len() as u64) {
+                return false;
+            }
         }
-
-        match writer.finalize() {
-            Ok(_) => return true,
-            Err(_) => return false,
-        };
     }
-}
\ No newline at end of file
+
+    writer.finalize().is_ok()
+}
@@ -0,0 +1,312 @@
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::utils::milliseconds_to_samples;
+
+
+const SOFTWARE_NAME: &str = "BitroSynth";
+
+#[derive(Clone)]
+pub struct CueMarker {
+    pub milliseconds: u32,
+    pub label: String,
+}
+
+#[derive(Clone)]
+pub struct WavMetadata {
+    pub title: String,
+    pub artist: String,
+    pub loop_points: Option<(u32, u32)>,
+    pub cue_markers: Vec<CueMarker>,
+    // marks where channels start and stop sounding and where sections begin, see render::boundary_markers
+    pub boundary_markers: bool,
+}
+
+impl WavMetadata {
+    pub const fn new() -> WavMetadata {
+        WavMetadata {
+            title: String::new(),
+            artist: String::new(),
+            loop_points: None,
+            cue_markers: Vec::new(),
+            boundary_markers: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.title.is_empty()
+            && self.artist.is_empty()
+            && self.loop_points.is_none()
+            && self.cue_markers.is_empty()
+            && !self.boundary_markers
+    }
+}
+
+impl Default for WavMetadata {
+    fn default() -> WavMetadata {
+        WavMetadata::new()
+    }
+}
+
+
+fn push_chunk(output: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    output.extend_from_slice(id);
+    output.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    output.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+fn zero_terminated(text: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = text.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+fn info_chunk(metadata: &WavMetadata) -> Vec<u8> {
+    let mut body: Vec<u8> = b"INFO".to_vec();
+    if !metadata.title.is_empty() {
+        push_chunk(&mut body, b"INAM", &zero_terminated(&metadata.title));
+    }
+    if !metadata.artist.is_empty() {
+        push_chunk(&mut body, b"IART", &zero_terminated(&metadata.artist));
+    }
+    push_chunk(&mut body, b"ISFT", &zero_terminated(SOFTWARE_NAME));
+
+    let mut output: Vec<u8> = Vec::new();
+    push_chunk(&mut output, b"LIST", &body);
+    output
+}
+
+fn cue_chunks(markers: &[(u32, String)]) -> Vec<u8> {
+    let mut cue: Vec<u8> = Vec::new();
+    cue.extend_from_slice(&(markers.len() as u32).to_le_bytes());
+
+    let mut labels: Vec<u8> = b"adtl".to_vec();
+
+    for (index, (position, label)) in markers.iter().enumerate() {
+        let id: u32 = index as u32 + 1;
+
+        cue.extend_from_slice(&id.to_le_bytes());
+        cue.extend_from_slice(&position.to_le_bytes());
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&0u32.to_le_bytes());
+        cue.extend_from_slice(&0u32.to_le_bytes());
+        cue.extend_from_slice(&position.to_le_bytes());
+
+        let mut label_body: Vec<u8> = id.to_le_bytes().to_vec();
+        label_body.extend(zero_terminated(label));
+        push_chunk(&mut labels, b"labl", &label_body);
+    }
+
+    let mut output: Vec<u8> = Vec::new();
+    push_chunk(&mut output, b"cue ", &cue);
+    push_chunk(&mut output, b"LIST", &labels);
+    output
+}
+
+fn sampler_chunk(loop_start: u32, loop_end: u32, sample_rate: u32) -> Vec<u8> {
+    let mut body: Vec<u8> = Vec::new();
+    let sample_period: u32 = 1_000_000_000 / sample_rate.max(1);
+
+    body.extend_from_slice(&0u32.to_le_bytes()); // manufacturer
+    body.extend_from_slice(&0u32.to_le_bytes()); // product
+    body.extend_from_slice(&sample_period.to_le_bytes());
+    body.extend_from_slice(&60u32.to_le_bytes()); // MIDI unity note
+    body.extend_from_slice(&0u32.to_le_bytes()); // pitch fraction
+    body.extend_from_slice(&0u32.to_le_bytes()); // SMPTE format
+    body.extend_from_slice(&0u32.to_le_bytes()); // SMPTE offset
+    body.extend_from_slice(&1u32.to_le_bytes()); // loop count
+    body.extend_from_slice(&0u32.to_le_bytes()); // sampler data
+
+    body.extend_from_slice(&0u32.to_le_bytes()); // cue point id
+    body.extend_from_slice(&0u32.to_le_bytes()); // forward loop
+    body.extend_from_slice(&loop_start.to_le_bytes());
+    body.extend_from_slice(&loop_end.to_le_bytes());
+    body.extend_from_slice(&0u32.to_le_bytes()); // fraction
+    body.extend_from_slice(&0u32.to_le_bytes()); // infinite play count
+
+    let mut output: Vec<u8> = Vec::new();
+    push_chunk(&mut output, b"smpl", &body);
+    output
+}
+
+
+// RIFF allows chunks after "data", so the metadata is appended to the finished file
+pub fn append_wav_metadata(
+    path: &str,
+    metadata: &WavMetadata,
+    boundaries: &[CueMarker],
+    sample_rate: u32,
+    total_samples: u32
+) -> bool {
+
+    if metadata.is_empty() {
+        return true;
+    }
+
+    let mut chunks: Vec<u8> = Vec::new();
+
+    if !metadata.title.is_empty() || !metadata.artist.is_empty() {
+        chunks.extend(info_chunk(metadata));
+    }
+
+    let position = |milliseconds: u32| -> u32 {
+        (milliseconds_to_samples(milliseconds, sample_rate as f32) as u32).min(total_samples)
+    };
+
+    let mut markers: Vec<(u32, String)> = metadata
+        .cue_markers
+        .iter()
+        .chain(boundaries.iter().filter(|_| metadata.boundary_markers))
+        .map(|m| (position(m.milliseconds), m.label.clone()))
+        .collect();
+
+    if let Some((start, end)) = metadata.loop_points {
+        let loop_start: u32 = position(start);
+        let loop_end: u32 = position(end);
+
+        if loop_end > loop_start {
+            markers.push((loop_start, String::from("Loop start")));
+            markers.push((loop_end, String::from("Loop end")));
+            // the smpl loop end is inclusive
+            chunks.extend(sampler_chunk(loop_start, loop_end - 1, sample_rate));
+        }
+    }
+
+    if !markers.is_empty() {
+        markers.sort_by_key(|(position, _)| *position);
+        chunks.extend(cue_chunks(&markers));
+    }
+
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+
+    let end: u64 = match file.seek(SeekFrom::End(0)) {
+        Ok(e) => e,
+        Err(_) => return false,
+    };
+
+    // odd sized 8-bit data is written without its pad byte, add it before the new chunks
+    if end % 2 == 1 {
+        chunks.insert(0, 0);
+    }
+    let riff_size: u64 = end - 8 + chunks.len() as u64;
+
+    if file.write_all(&chunks).is_err() {
+        return false;
+    }
+
+    if file.seek(SeekFrom::Start(4)).is_err() {
+        return false;
+    }
+
+    file.write_all(&(riff_size as u32).to_le_bytes()).is_ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn info_strings_are_terminated_and_padded() {
+        let metadata: WavMetadata = WavMetadata { title: String::from("Song"), ..WavMetadata::new() };
+        let chunk: Vec<u8> = info_chunk(&metadata);
+
+        let mut expected: Vec<u8> = b"LIST".to_vec();
+        expected.extend_from_slice(&38u32.to_le_bytes());
+        expected.extend_from_slice(b"INFOINAM\x05\0\0\0Song\0\0");
+        expected.extend_from_slice(b"ISFT\x0B\0\0\0BitroSynth\0\0");
+        assert_eq!(chunk, expected);
+    }
+
+    #[test]
+    fn cue_points_and_labels_share_their_ids() {
+        let chunk: Vec<u8> = cue_chunks(&[(0, String::from("A")), (4410, String::from("Loop"))]);
+
+        assert_eq!(&chunk[..4], b"cue ");
+        assert_eq!(u32_at(&chunk, 4), 4 + 2 * 24);
+        assert_eq!(u32_at(&chunk, 8), 2);
+
+        let second: &[u8] = &chunk[12 + 24..12 + 48];
+        assert_eq!(u32_at(second, 0), 2);
+        assert_eq!(u32_at(second, 4), 4410);
+        assert_eq!(&second[8..12], b"data");
+        assert_eq!(u32_at(second, 12), 0);
+        assert_eq!(u32_at(second, 16), 0);
+        assert_eq!(u32_at(second, 20), 4410);
+
+        let labels: &[u8] = &chunk[60..];
+        assert_eq!(&labels[..4], b"LIST");
+        assert_eq!(u32_at(labels, 4) as usize, labels.len() - 8);
+        assert_eq!(&labels[8..12], b"adtl");
+        assert_eq!(&labels[12..26], b"labl\x06\0\0\0\x01\0\0\0A\0");
+        assert_eq!(&labels[26..], b"labl\x09\0\0\0\x02\0\0\0Loop\0\0");
+    }
+
+    #[test]
+    fn sampler_chunk_holds_one_forward_loop() {
+        let chunk: Vec<u8> = sampler_chunk(100, 199, 44100);
+
+        assert_eq!(&chunk[..4], b"smpl");
+        assert_eq!(u32_at(&chunk, 4), 60);
+        assert_eq!(u32_at(&chunk, 16), 22675);
+        assert_eq!(u32_at(&chunk, 20), 60);
+        assert_eq!(u32_at(&chunk, 36), 1);
+        assert_eq!(u32_at(&chunk, 48), 0);
+        assert_eq!(u32_at(&chunk, 52), 100);
+        assert_eq!(u32_at(&chunk, 56), 199);
+        assert_eq!(chunk.len(), 68);
+    }
+
+    #[test]
+    fn metadata_is_appended_after_the_data_chunk() {
+        // a mono 8 bit file with three samples, written without the data pad byte
+        let mut wav: Vec<u8> = b"RIFF".to_vec();
+        wav.extend_from_slice(&39u32.to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt \x10\0\0\0\x01\0\x01\0\xE8\x03\0\0\xE8\x03\0\0\x01\0\x08\0");
+        wav.extend_from_slice(b"data\x03\0\0\0\x80\x80\x80");
+        let path = std::env::temp_dir().join("bitrosynth_wav_metadata_test.wav");
+        std::fs::write(&path, &wav).unwrap();
+
+        let metadata: WavMetadata = WavMetadata {
+            loop_points: Some((1, 2000)),
+            boundary_markers: true,
+            ..WavMetadata::new()
+        };
+        let boundaries: Vec<CueMarker> = vec![CueMarker { milliseconds: 2, label: String::from("Channel 1 end") }];
+        assert!(append_wav_metadata(&path.to_string_lossy(), &metadata, &boundaries, 1000, 3));
+        let bytes: Vec<u8> = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(&bytes[8..wav.len()], &wav[8..]);
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(bytes[wav.len()], 0);
+
+        // the loop end lies past the song and is clamped to its length
+        let smpl: usize = wav.len() + 1;
+        assert_eq!(&bytes[smpl..smpl + 4], b"smpl");
+        assert_eq!(u32_at(&bytes, smpl + 52), 1);
+        assert_eq!(u32_at(&bytes, smpl + 56), 2);
+
+        let cue: usize = smpl + 68;
+        assert_eq!(&bytes[cue..cue + 4], b"cue ");
+        assert_eq!(u32_at(&bytes, cue + 8), 3);
+        let positions: Vec<u32> = (0..3).map(|n| u32_at(&bytes, cue + 12 + n * 24 + 4)).collect();
+        assert_eq!(positions, [1, 2, 3]);
+    }
+
+    #[test]
+    fn boundaries_are_ignored_unless_enabled() {
+        let boundaries: Vec<CueMarker> = vec![CueMarker { milliseconds: 2, label: String::from("Section 2") }];
+        assert!(append_wav_metadata("/nonexistent/bitrosynth.wav", &WavMetadata::new(), &boundaries, 1000, 3));
+    }
+}
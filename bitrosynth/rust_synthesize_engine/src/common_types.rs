@@ -1,15 +1,293 @@
+use serde::{Deserialize, Serialize};
+
+
 #[repr(i32)]
+#[derive(Clone, Copy)]
 pub enum CommandType {
     None = 0,
     SynthesizeAudio = 1,
     Set8BitStatus = 2,
     GetSampleRate = 3,
-    SetSampleRate = 4
+    SetSampleRate = 4,
+    AddChannelEffect = 5,
+    ClearChannelEffects = 6,
+    AddChannelWaveshaper = 7,
+    SetDitherMode = 8,
+    SetExportFormat = 9,
+    SetContainerFormat = 10,
+    SetRawPcmOptions = 11,
+    SetWavMetadata = 12,
+    SynthesizeToBuffer = 13,
+    FreeRenderBuffer = 14,
+    ClearRenderCache = 15,
+    SynthesizeWithConfig = 16,
+    LoadProject = 17,
+    SaveProject = 18,
+    MigrateProject = 19,
+    SynthesizeProject = 20,
+    ImportMidi = 21,
+    ExportMidi = 22,
+    ImportMod = 23,
+    ClearSamples = 24,
+    ImportMml = 25,
+    RegisterInstrument = 26,
+    RemoveInstrument = 27,
+    ClearInstruments = 28,
+    LoadSample = 29,
+    SetChipProfile = 30,
+    ValidateChipSong = 31
 }
 
 #[repr(i32)]
+#[derive(Clone, Copy)]
 pub enum ProcessStatus {
     InProgress = 1,
     Success = 0,
     Error = -1,
-}
\ No newline at end of file
+    Cancelled = 2,
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy)]
+pub enum EffectType {
+    Chorus = 0,
+    Flanger = 1,
+    Phaser = 2
+}
+
+impl EffectType {
+    pub fn from_i32(value: i32) -> Option<EffectType> {
+        match value {
+            0 => Some(EffectType::Chorus),
+            1 => Some(EffectType::Flanger),
+            2 => Some(EffectType::Phaser),
+            _ => None,
+        }
+    }
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveshaperShape {
+    SoftClip = 0,
+    HardClip = 1,
+    Foldback = 2,
+    Tube = 3
+}
+
+impl WaveshaperShape {
+    pub fn from_i32(value: i32) -> Option<WaveshaperShape> {
+        match value {
+            0 => Some(WaveshaperShape::SoftClip),
+            1 => Some(WaveshaperShape::HardClip),
+            2 => Some(WaveshaperShape::Foldback),
+            3 => Some(WaveshaperShape::Tube),
+            _ => None,
+        }
+    }
+}
+
+// sound chip a render is constrained to, see synth/chip.rs
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChipProfile {
+    None = 0,
+    Nes = 1,
+    GameBoy = 2,
+    Sn76489 = 3
+}
+
+impl ChipProfile {
+    pub fn from_i32(value: i32) -> Option<ChipProfile> {
+        match value {
+            0 => Some(ChipProfile::None),
+            1 => Some(ChipProfile::Nes),
+            2 => Some(ChipProfile::GameBoy),
+            3 => Some(ChipProfile::Sn76489),
+            _ => None,
+        }
+    }
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DitherMode {
+    None = 0,
+    Tpdf = 1,
+    TpdfNoiseShaped = 2
+}
+
+impl DitherMode {
+    pub fn from_i32(value: i32) -> Option<DitherMode> {
+        match value {
+            0 => Some(DitherMode::None),
+            1 => Some(DitherMode::Tpdf),
+            2 => Some(DitherMode::TpdfNoiseShaped),
+            _ => None,
+        }
+    }
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Int8 = 0,
+    Int16 = 1,
+    Int24 = 2,
+    Int32 = 3,
+    Float32 = 4
+}
+
+impl ExportFormat {
+    pub fn from_i32(value: i32) -> Option<ExportFormat> {
+        match value {
+            0 => Some(ExportFormat::Int8),
+            1 => Some(ExportFormat::Int16),
+            2 => Some(ExportFormat::Int24),
+            3 => Some(ExportFormat::Int32),
+            4 => Some(ExportFormat::Float32),
+            _ => None,
+        }
+    }
+
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            ExportFormat::Int8 => 8,
+            ExportFormat::Int16 => 16,
+            ExportFormat::Int24 => 24,
+            ExportFormat::Int32 | ExportFormat::Float32 => 32,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        self == ExportFormat::Float32
+    }
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerFormat {
+    Auto = 0,
+    Wav = 1,
+    Flac = 2,
+    Aiff = 3,
+    AiffC = 4,
+    Raw = 5
+}
+
+impl ContainerFormat {
+    pub fn from_i32(value: i32) -> Option<ContainerFormat> {
+        match value {
+            0 => Some(ContainerFormat::Auto),
+            1 => Some(ContainerFormat::Wav),
+            2 => Some(ContainerFormat::Flac),
+            3 => Some(ContainerFormat::Aiff),
+            4 => Some(ContainerFormat::AiffC),
+            5 => Some(ContainerFormat::Raw),
+            _ => None,
+        }
+    }
+
+    pub fn resolve(self, path: &str) -> ContainerFormat {
+        if self != ContainerFormat::Auto {
+            return self;
+        }
+
+        let extension: String = std::path::Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            "flac" => ContainerFormat::Flac,
+            "aif" | "aiff" => ContainerFormat::Aiff,
+            "aifc" => ContainerFormat::AiffC,
+            "raw" | "pcm" => ContainerFormat::Raw,
+            _ => ContainerFormat::Wav,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Waveform {
+    Triangle,
+    Sine,
+    Square,
+    Sawtooth,
+    WhiteNoise,
+    PinkNoise,
+    Silence,
+    // percussion generators, see synth/drums.rs
+    Kick,
+    Snare,
+    ClosedHiHat,
+    OpenHiHat,
+    Tom,
+    Clap,
+    // frequency modulation voices with two or four operators, see synth/oscillators.rs
+    Fm2,
+    Fm4,
+    // slot in the sample bank, see synth/sampler.rs
+    Sample(u16)
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "Triangle" => Some(Waveform::Triangle),
+            "Sine" => Some(Waveform::Sine),
+            "Square" => Some(Waveform::Square),
+            "Sawtooth" => Some(Waveform::Sawtooth),
+            "WhiteNoise" => Some(Waveform::WhiteNoise),
+            "PinkNoise" => Some(Waveform::PinkNoise),
+            "Silence" => Some(Waveform::Silence),
+            "Kick" => Some(Waveform::Kick),
+            "Snare" => Some(Waveform::Snare),
+            "ClosedHiHat" => Some(Waveform::ClosedHiHat),
+            "OpenHiHat" => Some(Waveform::OpenHiHat),
+            "Tom" => Some(Waveform::Tom),
+            "Clap" => Some(Waveform::Clap),
+            "Fm2" => Some(Waveform::Fm2),
+            "Fm4" => Some(Waveform::Fm4),
+            _ => name.strip_prefix("Sample")?.parse::<u16>().ok().map(Waveform::Sample),
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Waveform::Triangle => String::from("Triangle"),
+            Waveform::Sine => String::from("Sine"),
+            Waveform::Square => String::from("Square"),
+            Waveform::Sawtooth => String::from("Sawtooth"),
+            Waveform::WhiteNoise => String::from("WhiteNoise"),
+            Waveform::PinkNoise => String::from("PinkNoise"),
+            Waveform::Silence => String::from("Silence"),
+            Waveform::Kick => String::from("Kick"),
+            Waveform::Snare => String::from("Snare"),
+            Waveform::ClosedHiHat => String::from("ClosedHiHat"),
+            Waveform::OpenHiHat => String::from("OpenHiHat"),
+            Waveform::Tom => String::from("Tom"),
+            Waveform::Clap => String::from("Clap"),
+            Waveform::Fm2 => String::from("Fm2"),
+            Waveform::Fm4 => String::from("Fm4"),
+            Waveform::Sample(slot) => format!("Sample{}", slot),
+        }
+    }
+
+    pub fn is_fm(self) -> bool {
+        matches!(self, Waveform::Fm2 | Waveform::Fm4)
+    }
+
+    pub fn is_drum(self) -> bool {
+        matches!(
+            self,
+            Waveform::Kick | Waveform::Snare | Waveform::ClosedHiHat | Waveform::OpenHiHat | Waveform::Tom | Waveform::Clap
+        )
+    }
+}
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common_types::WaveshaperShape;
+
+use super::filter::{Biquad, DcBlocker, FilterType};
+
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct WaveshaperSettings {
+    pub shape: WaveshaperShape,
+    pub drive: f32,
+    pub output_gain: f32,
+    pub oversampling: u32,
+}
+
+// bias used by the tube curve, chosen so even harmonics are audible but not dominant
+const TUBE_BIAS: f32 = 0.2;
+
+fn shape_sample(shape: WaveshaperShape, x: f32) -> f32 {
+    match shape {
+        WaveshaperShape::SoftClip => x.tanh(),
+        WaveshaperShape::HardClip => x.clamp(-1.0, 1.0),
+        WaveshaperShape::Foldback => {
+            let t: f32 = 0.25 * x + 0.25;
+            4.0 * (t - t.round()).abs() - 1.0
+        }
+        WaveshaperShape::Tube => {
+            if x >= 0.0 {
+                (x + TUBE_BIAS).tanh() - TUBE_BIAS.tanh()
+            } else {
+                // the negative half saturates earlier, like a single-ended triode stage
+                0.6 * ((x / 0.6 + TUBE_BIAS).tanh() - TUBE_BIAS.tanh())
+            }
+        }
+    }
+}
+
+
+pub struct Waveshaper {
+    settings: WaveshaperSettings,
+    factor: u32,
+    previous_input: f32,
+    upsample_filters: [Biquad; 2],
+    downsample_filters: [Biquad; 2],
+    dc_blocker: DcBlocker,
+}
+
+impl Waveshaper {
+    pub fn new(settings: WaveshaperSettings, sample_rate: f32) -> Waveshaper {
+        let factor: u32 = match settings.oversampling {
+            0 | 1 => 1,
+            2 | 3 => 2,
+            4..=7 => 4,
+            _ => 8,
+        };
+
+        let oversampled_rate: f32 = sample_rate * factor as f32;
+        let cutoff: f32 = sample_rate * 0.45;
+        let filter = || Biquad::new(FilterType::LowPass, cutoff, 0.707, oversampled_rate);
+
+        Waveshaper {
+            settings: WaveshaperSettings {
+                shape: settings.shape,
+                drive: settings.drive.clamp(0.1, 100.0),
+                output_gain: settings.output_gain.clamp(0.0, 4.0),
+                oversampling: factor,
+            },
+            factor,
+            previous_input: 0.0,
+            upsample_filters: [filter(), filter()],
+            downsample_filters: [filter(), filter()],
+            dc_blocker: DcBlocker::new(),
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let mut output: f32 = 0.0;
+
+        if self.factor == 1 {
+            output = shape_sample(self.settings.shape, input * self.settings.drive);
+        } else {
+            for step in 1..=self.factor {
+                let position: f32 = step as f32 / self.factor as f32;
+                let mut upsampled: f32 = self.previous_input + (input - self.previous_input) * position;
+                for filter in self.upsample_filters.iter_mut() {
+                    upsampled = filter.process(upsampled);
+                }
+
+                let mut shaped: f32 = shape_sample(self.settings.shape, upsampled * self.settings.drive);
+                for filter in self.downsample_filters.iter_mut() {
+                    shaped = filter.process(shaped);
+                }
+
+                // decimate by keeping the last sample of each block
+                output = shaped;
+            }
+        }
+
+        self.previous_input = input;
+
+        let output: f32 = match self.settings.shape {
+            WaveshaperShape::Tube => self.dc_blocker.process(output),
+            _ => output,
+        };
+
+        output * self.settings.output_gain
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shaper(shape: WaveshaperShape, drive: f32, oversampling: u32) -> Waveshaper {
+        Waveshaper::new(WaveshaperSettings { shape, drive, output_gain: 1.0, oversampling }, 44100.0)
+    }
+
+    fn sine(frequency: f32, amplitude: f32) -> Vec<f32> {
+        (0..4410).map(|n| amplitude * (2.0 * std::f32::consts::PI * frequency * n as f32 / 44100.0).sin()).collect()
+    }
+
+    #[test]
+    fn curves_stay_within_full_scale() {
+        let shapes: [WaveshaperShape; 4] =
+            [WaveshaperShape::SoftClip, WaveshaperShape::HardClip, WaveshaperShape::Foldback, WaveshaperShape::Tube];
+
+        for shape in shapes {
+            for step in -400..=400 {
+                let value: f32 = shape_sample(shape, step as f32 / 10.0);
+                assert!(value.abs() <= 1.0, "{} at {}", value, step);
+            }
+            assert_eq!(shape_sample(shape, 0.0), 0.0);
+        }
+
+        assert_eq!(shape_sample(WaveshaperShape::HardClip, 3.0), 1.0);
+        assert!((shape_sample(WaveshaperShape::Foldback, 1.5) - 0.5).abs() < 1e-6);
+        // the tube curve is asymmetric, its negative half flattens first
+        assert!(shape_sample(WaveshaperShape::Tube, -2.0).abs() < shape_sample(WaveshaperShape::Tube, 2.0));
+    }
+
+    #[test]
+    fn drive_pushes_the_signal_into_the_curve() {
+        let input: Vec<f32> = sine(220.0, 0.5);
+        let mut clean: Waveshaper = shaper(WaveshaperShape::HardClip, 1.0, 1);
+        let mut driven: Waveshaper = shaper(WaveshaperShape::HardClip, 4.0, 1);
+
+        assert!(input.iter().all(|&s| clean.process(s) == s));
+        let output: Vec<f32> = input.iter().map(|&s| driven.process(s)).collect();
+        assert_eq!(output.iter().fold(0.0f32, |a, b| a.max(b.abs())), 1.0);
+        assert!(output.iter().filter(|s| s.abs() == 1.0).count() > 2000);
+    }
+
+    #[test]
+    fn oversampling_rounds_up_to_a_power_of_two_and_stays_bounded() {
+        for (requested, factor) in [(0, 1), (3, 2), (5, 4), (64, 8)] {
+            let mut waveshaper: Waveshaper = shaper(WaveshaperShape::SoftClip, 20.0, requested);
+            assert_eq!(waveshaper.factor, factor);
+
+            // the anti-aliasing filters ring on the driven edges, apply_effect_chain clamps what is left
+            let limit: f32 = if factor == 1 { 1.0 } else { 1.25 };
+            let peak: f32 = sine(5000.0, 1.0).iter().fold(0.0, |a, &s| a.max(waveshaper.process(s).abs()));
+            assert!(peak <= limit, "{} with factor {}", peak, factor);
+        }
+    }
+
+    #[test]
+    fn tube_output_has_no_dc_offset() {
+        let mut waveshaper: Waveshaper = shaper(WaveshaperShape::Tube, 4.0, 1);
+        let output: Vec<f32> = sine(441.0, 1.0).iter().map(|&s| waveshaper.process(s)).collect();
+        let mean: f32 = output[2205..].iter().sum::<f32>() / 2205.0;
+        assert!(mean.abs() < 0.01, "{}", mean);
+    }
+}
@@ -0,0 +1,136 @@
+use std::f32::consts;
+
+
+#[derive(Clone, Copy)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+}
+
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    pub fn new(filter_type: FilterType, cutoff: f32, q: f32, sample_rate: f32) -> Biquad {
+        // max keeps the range valid at rates too low for a 10 Hz cutoff
+        let cutoff: f32 = cutoff.clamp(10.0, (sample_rate * 0.49).max(10.0));
+        let omega: f32 = 2.0 * consts::PI * cutoff / sample_rate;
+        let alpha: f32 = omega.sin() / (2.0 * q.max(0.01));
+        let cos_omega: f32 = omega.cos();
+
+        let (b0, b1, b2): (f32, f32, f32) = match filter_type {
+            FilterType::LowPass => ((1.0 - cos_omega) / 2.0, 1.0 - cos_omega, (1.0 - cos_omega) / 2.0),
+            FilterType::HighPass => ((1.0 + cos_omega) / 2.0, -(1.0 + cos_omega), (1.0 + cos_omega) / 2.0),
+            FilterType::BandPass => (alpha, 0.0, -alpha),
+        };
+        let a0: f32 = 1.0 + alpha;
+
+        Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: -2.0 * cos_omega / a0,
+            a2: (1.0 - alpha) / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output: f32 = self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1 - self.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = input;
+        self.y2 = self.y1;
+        self.y1 = output;
+
+        output
+    }
+}
+
+
+pub struct DcBlocker {
+    x1: f32,
+    y1: f32,
+}
+
+impl DcBlocker {
+    pub fn new() -> DcBlocker {
+        DcBlocker { x1: 0.0, y1: 0.0 }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output: f32 = input - self.x1 + 0.995 * self.y1;
+        self.x1 = input;
+        self.y1 = output;
+
+        output
+    }
+}
+
+impl Default for DcBlocker {
+    fn default() -> DcBlocker {
+        DcBlocker::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // level of a sine after the filter has settled, relative to its input level
+    fn gain_at(filter_type: FilterType, cutoff: f32, frequency: f32) -> f32 {
+        let sample_rate: f32 = 44100.0;
+        let mut filter: Biquad = Biquad::new(filter_type, cutoff, 0.707, sample_rate);
+        let output: Vec<f32> = (0..8820)
+            .map(|n| filter.process((2.0 * consts::PI * frequency * n as f32 / sample_rate).sin()))
+            .collect();
+
+        let settled: &[f32] = &output[4410..];
+        (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32 * 2.0).sqrt()
+    }
+
+    #[test]
+    fn low_pass_keeps_the_passband_and_attenuates_above_the_cutoff() {
+        assert!((gain_at(FilterType::LowPass, 1000.0, 100.0) - 1.0).abs() < 0.02);
+        assert!((gain_at(FilterType::LowPass, 1000.0, 1000.0) - 0.707).abs() < 0.02);
+        assert!(gain_at(FilterType::LowPass, 1000.0, 10000.0) < 0.02);
+    }
+
+    #[test]
+    fn high_pass_and_band_pass_shape_the_opposite_ends() {
+        assert!(gain_at(FilterType::HighPass, 1000.0, 100.0) < 0.02);
+        assert!((gain_at(FilterType::HighPass, 1000.0, 10000.0) - 1.0).abs() < 0.02);
+        assert!((gain_at(FilterType::BandPass, 1000.0, 1000.0) - 1.0).abs() < 0.02);
+        assert!(gain_at(FilterType::BandPass, 1000.0, 10000.0) < 0.2);
+    }
+
+    #[test]
+    fn dc_blocker_removes_an_offset() {
+        let mut blocker: DcBlocker = DcBlocker::new();
+        let output: Vec<f32> = (0..4410).map(|_| blocker.process(0.5)).collect();
+        assert_eq!(output[0], 0.5);
+        assert!(output[4409].abs() < 0.001);
+    }
+
+    #[test]
+    fn rates_below_the_cutoff_range_do_not_panic() {
+        for sample_rate in [0.0, 10.0, 20.0] {
+            Biquad::new(FilterType::LowPass, 1000.0, 0.707, sample_rate);
+        }
+    }
+}
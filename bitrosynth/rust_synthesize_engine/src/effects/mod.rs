@@ -0,0 +1,66 @@
+pub mod modulation;
+pub mod distortion;
+pub mod filter;
+
+use serde::{Deserialize, Serialize};
+
+use modulation::{ModulatedDelay, ModulationSettings, Phaser};
+use distortion::{Waveshaper, WaveshaperSettings};
+
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EffectSettings {
+    Chorus(ModulationSettings),
+    Flanger(ModulationSettings),
+    Phaser(ModulationSettings),
+    Waveshaper(WaveshaperSettings),
+}
+
+pub enum EffectProcessor {
+    ModulatedDelay(ModulatedDelay),
+    Phaser(Phaser),
+    Waveshaper(Waveshaper),
+}
+
+impl EffectProcessor {
+    pub fn new(settings: EffectSettings, sample_rate: f32) -> EffectProcessor {
+        match settings {
+            EffectSettings::Chorus(s) => EffectProcessor::ModulatedDelay(ModulatedDelay::chorus(s, sample_rate)),
+            EffectSettings::Flanger(s) => EffectProcessor::ModulatedDelay(ModulatedDelay::flanger(s, sample_rate)),
+            EffectSettings::Phaser(s) => EffectProcessor::Phaser(Phaser::new(s, sample_rate)),
+            EffectSettings::Waveshaper(s) => EffectProcessor::Waveshaper(Waveshaper::new(s, sample_rate)),
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        match self {
+            EffectProcessor::ModulatedDelay(p) => p.process(input),
+            EffectProcessor::Phaser(p) => p.process(input),
+            EffectProcessor::Waveshaper(p) => p.process(input),
+        }
+    }
+}
+
+
+// a waveshaper is meant to be driven, so its channel reaches it without clipping or normalising
+pub fn has_waveshaper(chain: &[EffectSettings]) -> bool {
+    chain.iter().any(|e| matches!(e, EffectSettings::Waveshaper(_)))
+}
+
+pub fn apply_effect_chain(
+    samples: &mut [f32],
+    chain: &[EffectSettings],
+    sample_rate: f32
+) {
+    for settings in chain {
+        let mut processor: EffectProcessor = EffectProcessor::new(*settings, sample_rate);
+        for sample in samples.iter_mut() {
+            *sample = processor.process(*sample);
+        }
+    }
+
+    for sample in samples.iter_mut() {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+}
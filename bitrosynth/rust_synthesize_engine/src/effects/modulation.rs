@@ -0,0 +1,211 @@
+use std::f32::consts;
+
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct ModulationSettings {
+    pub rate: f32,
+    pub depth: f32,
+    pub feedback: f32,
+    pub mix: f32,
+}
+
+impl ModulationSettings {
+    pub fn clamped(self) -> ModulationSettings {
+        ModulationSettings {
+            rate: self.rate.clamp(0.01, 20.0),
+            depth: self.depth.clamp(0.0, 1.0),
+            feedback: self.feedback.clamp(-0.95, 0.95),
+            mix: self.mix.clamp(0.0, 1.0),
+        }
+    }
+}
+
+
+// ------------------------------------------------------------------------------
+// Chorus / Flanger
+
+pub struct ModulatedDelay {
+    settings: ModulationSettings,
+    sample_rate: f32,
+    buffer: Vec<f32>,
+    write_index: usize,
+    lfo_phase: f32,
+    base_delay: f32,
+    delay_swing: f32,
+    voice_offsets: Vec<f32>,
+    last_output: f32,
+}
+
+impl ModulatedDelay {
+    pub fn chorus(settings: ModulationSettings, sample_rate: f32) -> ModulatedDelay {
+        // three taps spread over the LFO cycle for a thicker ensemble
+        ModulatedDelay::new(settings, sample_rate, 0.015, 0.008, vec![0.0, 1.0 / 3.0, 2.0 / 3.0])
+    }
+
+    pub fn flanger(settings: ModulationSettings, sample_rate: f32) -> ModulatedDelay {
+        ModulatedDelay::new(settings, sample_rate, 0.001, 0.004, vec![0.0])
+    }
+
+    fn new(
+        settings: ModulationSettings,
+        sample_rate: f32,
+        base_seconds: f32,
+        swing_seconds: f32,
+        voice_offsets: Vec<f32>,
+    ) -> ModulatedDelay {
+        let settings: ModulationSettings = settings.clamped();
+        let base_delay: f32 = base_seconds * sample_rate;
+        let delay_swing: f32 = swing_seconds * sample_rate * settings.depth;
+        let buffer_length: usize = (base_delay + delay_swing) as usize + 4;
+
+        ModulatedDelay {
+            settings,
+            sample_rate,
+            buffer: vec![0.0; buffer_length],
+            write_index: 0,
+            lfo_phase: 0.0,
+            base_delay,
+            delay_swing,
+            voice_offsets,
+            last_output: 0.0,
+        }
+    }
+
+    fn read_delayed(&self, delay: f32) -> f32 {
+        let length: usize = self.buffer.len();
+        let read_position: f32 = self.write_index as f32 - delay;
+        let wrapped: f32 = read_position.rem_euclid(length as f32);
+
+        let index_a: usize = wrapped as usize % length;
+        let index_b: usize = (index_a + 1) % length;
+        let fraction: f32 = wrapped.fract();
+
+        self.buffer[index_a] * (1.0 - fraction) + self.buffer[index_b] * fraction
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let length: usize = self.buffer.len();
+        self.buffer[self.write_index] = input + self.last_output * self.settings.feedback;
+
+        let mut wet: f32 = 0.0;
+        for offset in self.voice_offsets.iter() {
+            let lfo: f32 = (2.0 * consts::PI * (self.lfo_phase + offset)).sin();
+            let delay: f32 = self.base_delay + self.delay_swing * 0.5 * (1.0 + lfo);
+            wet += self.read_delayed(delay.max(1.0));
+        }
+        wet /= self.voice_offsets.len() as f32;
+
+        self.last_output = wet;
+        self.write_index = (self.write_index + 1) % length;
+        self.lfo_phase = (self.lfo_phase + self.settings.rate / self.sample_rate).fract();
+
+        input * (1.0 - self.settings.mix) + wet * self.settings.mix
+    }
+}
+
+
+// ------------------------------------------------------------------------------
+// Phaser
+
+const PHASER_STAGES: usize = 6;
+const PHASER_MIN_FREQUENCY: f32 = 200.0;
+const PHASER_MAX_FREQUENCY: f32 = 4000.0;
+
+pub struct Phaser {
+    settings: ModulationSettings,
+    sample_rate: f32,
+    lfo_phase: f32,
+    stage_inputs: [f32; PHASER_STAGES],
+    stage_outputs: [f32; PHASER_STAGES],
+    last_output: f32,
+}
+
+impl Phaser {
+    pub fn new(settings: ModulationSettings, sample_rate: f32) -> Phaser {
+        Phaser {
+            settings: settings.clamped(),
+            sample_rate,
+            lfo_phase: 0.0,
+            stage_inputs: [0.0; PHASER_STAGES],
+            stage_outputs: [0.0; PHASER_STAGES],
+            last_output: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let lfo: f32 = 0.5 * (1.0 + (2.0 * consts::PI * self.lfo_phase).sin());
+
+        // exponential sweep so the notch movement sounds even across the range
+        let max_frequency: f32 = PHASER_MIN_FREQUENCY
+            * (PHASER_MAX_FREQUENCY / PHASER_MIN_FREQUENCY).powf(self.settings.depth);
+        let frequency: f32 = (PHASER_MIN_FREQUENCY * (max_frequency / PHASER_MIN_FREQUENCY).powf(lfo))
+            .min(self.sample_rate * 0.45);
+
+        let tangent: f32 = (consts::PI * frequency / self.sample_rate).tan();
+        let coefficient: f32 = (tangent - 1.0) / (tangent + 1.0);
+
+        let mut signal: f32 = input + self.last_output * self.settings.feedback;
+        for stage in 0..PHASER_STAGES {
+            let output: f32 = coefficient * signal + self.stage_inputs[stage]
+                - coefficient * self.stage_outputs[stage];
+            self.stage_inputs[stage] = signal;
+            self.stage_outputs[stage] = output;
+            signal = output;
+        }
+
+        self.last_output = signal;
+        self.lfo_phase = (self.lfo_phase + self.settings.rate / self.sample_rate).fract();
+
+        input * (1.0 - self.settings.mix) + signal * self.settings.mix
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(feedback: f32, mix: f32) -> ModulationSettings {
+        ModulationSettings { rate: 0.5, depth: 1.0, feedback, mix }
+    }
+
+    fn impulse_response(mut process: impl FnMut(f32) -> f32, length: usize) -> Vec<f32> {
+        (0..length).map(|n| process(if n == 0 { 1.0 } else { 0.0 })).collect()
+    }
+
+    #[test]
+    fn dry_mix_passes_the_input_through() {
+        let input: Vec<f32> = (0..1000).map(|n| (n as f32 * 0.05).sin()).collect();
+
+        let mut chorus: ModulatedDelay = ModulatedDelay::chorus(settings(0.5, 0.0), 44100.0);
+        let mut phaser: Phaser = Phaser::new(settings(0.5, 0.0), 44100.0);
+        for &sample in input.iter() {
+            assert_eq!(chorus.process(sample), sample);
+            assert_eq!(phaser.process(sample), sample);
+        }
+    }
+
+    #[test]
+    fn flanger_echoes_within_its_delay_range() {
+        let mut flanger: ModulatedDelay = ModulatedDelay::flanger(settings(0.0, 1.0), 44100.0);
+        let response: Vec<f32> = impulse_response(|s| flanger.process(s), 441);
+
+        // 1 ms base delay and up to 4 ms of swing at full depth
+        let first_echo: usize = response.iter().position(|s| s.abs() > 0.01).unwrap();
+        assert!((43..=44 + 176).contains(&first_echo), "{}", first_echo);
+        assert!(response.iter().all(|s| s.abs() <= 1.0));
+    }
+
+    #[test]
+    fn feedback_is_clamped_so_the_echoes_die_out() {
+        let mut flanger: ModulatedDelay = ModulatedDelay::flanger(settings(5.0, 1.0), 44100.0);
+        let response: Vec<f32> = impulse_response(|s| flanger.process(s), 44100);
+        assert!(response[44000..].iter().all(|s| s.abs() < 0.01));
+
+        let mut phaser: Phaser = Phaser::new(settings(-5.0, 1.0), 44100.0);
+        let response: Vec<f32> = impulse_response(|s| phaser.process(s), 44100);
+        assert!(response[44000..].iter().all(|s| s.abs() < 0.01));
+    }
+}
@@ -1,11 +1,29 @@
-use std::ffi::{c_char, c_int, c_uchar, c_uint};
+use std::ffi::{CString, c_char, c_float, c_int, c_uchar, c_uint};
+use std::fs;
+use std::sync::Arc;
 use std::thread;
 use std::sync::atomic::Ordering;
 
-use crate::audio::wav::write_wav;
-use crate::common_types::{CommandType, ProcessStatus};
-use crate::utils::{c_char_to_string, set_status};
-use crate::synth::channel::generate_channel;
+use crate::audio::{format_error, write_audio};
+use crate::audio::wav_metadata::{CueMarker, WavMetadata};
+use crate::common_types::{ChipProfile, CommandType, ContainerFormat, DitherMode, EffectType, ExportFormat, ProcessStatus, Waveform, WaveshaperShape};
+use crate::effects::EffectSettings;
+use crate::effects::distortion::WaveshaperSettings;
+use crate::effects::modulation::ModulationSettings;
+use crate::mml::parse_mml;
+use crate::progress::Progress;
+use crate::protracker::import_mod as import_mod_file;
+use crate::project::{DEFAULT_TEMPO, ImportedSong, LoadedProject, Project};
+use crate::midi::export::write_midi;
+use crate::midi::import::import_midi as import_midi_file;
+use crate::jobs::{self, active_job_count, job_command, job_error, job_progress, job_status};
+use crate::render::{RenderBuffer, RenderBufferSlot, RenderedSong, render_song};
+use crate::render_cache::clear_row_cache;
+use crate::render_config::RenderConfig;
+use crate::synth::chip::plan_song;
+use crate::synth::instrument::{self, Instrument};
+use crate::synth::sampler::{Sample, SampleFile, add_sample, clear_samples as clear_sample_bank};
+use crate::utils::{begin_job, c_char_to_string, c_song_to_vec, end_job, is_valid_sample_rate, set_status};
 use crate::global_state::*;
 
 
@@ -28,19 +46,219 @@ pub extern "C" fn get_current_command() -> c_int {
 pub extern "C" fn set_8_bit_status(new_status: c_uchar){
     set_status(ProcessStatus::InProgress,CommandType::Set8BitStatus);
     if new_status == 1{
-        BIT_8_STATUS.store(true, Ordering::SeqCst);
+        EXPORT_FORMAT.store(ExportFormat::Int8 as i32, Ordering::SeqCst);
     }
     else{
-
-        BIT_8_STATUS.store(false, Ordering::SeqCst);
+        EXPORT_FORMAT.store(ExportFormat::Int16 as i32, Ordering::SeqCst);
     }
     set_status(ProcessStatus::Success,CommandType::None);
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn set_export_format(format: c_int) {
+    set_status(ProcessStatus::InProgress, CommandType::SetExportFormat);
+
+    let export_format: ExportFormat = match ExportFormat::from_i32(format) {
+        Some(f) => f,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    EXPORT_FORMAT.store(export_format as i32, Ordering::SeqCst);
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_export_format() -> c_int {
+    EXPORT_FORMAT.load(Ordering::SeqCst) as c_int
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn set_container_format(format: c_int) {
+    set_status(ProcessStatus::InProgress, CommandType::SetContainerFormat);
+
+    let container_format: ContainerFormat = match ContainerFormat::from_i32(format) {
+        Some(f) => f,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    CONTAINER_FORMAT.store(container_format as i32, Ordering::SeqCst);
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+
+// Off by default, TPDF dither is meant for 8 and 16 bit exports where the rounding error is audible
+#[unsafe(no_mangle)]
+pub extern "C" fn set_dither_mode(mode: c_int) {
+    set_status(ProcessStatus::InProgress, CommandType::SetDitherMode);
+
+    let dither_mode: DitherMode = match DitherMode::from_i32(mode) {
+        Some(m) => m,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    DITHER_MODE.store(dither_mode as i32, Ordering::SeqCst);
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+
+#[unsafe(no_mangle)]
+pub extern "C" fn set_chip_profile(profile: c_int) {
+    set_status(ProcessStatus::InProgress, CommandType::SetChipProfile);
+
+    let chip_profile: ChipProfile = match ChipProfile::from_i32(profile) {
+        Some(p) => p,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    CHIP_PROFILE.store(chip_profile as i32, Ordering::SeqCst);
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_chip_profile() -> c_int {
+    CHIP_PROFILE.load(Ordering::SeqCst) as c_int
+}
+
+// Returns how many warnings the song raises on the current chip profile, none without a profile
+#[unsafe(no_mangle)]
+pub extern "C" fn validate_chip_song(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+) -> c_uint {
+    set_status(ProcessStatus::InProgress, CommandType::ValidateChipSong);
+
+    let warnings: Option<Vec<String>> = c_song_to_vec(data, sizes_array, outer_size).and_then(|all_notes| {
+        let config: RenderConfig = RenderConfig::from_globals()?;
+        match config.chip_profile {
+            ChipProfile::None => Some(Vec::new()),
+            _ => plan_song(&all_notes, &config).map(|plan| plan.warnings),
+        }
+    });
+
+    // warnings of an earlier song must not be read as belonging to this one
+    let stored: Option<usize> = CHIP_WARNINGS.lock().ok().map(|mut stored| {
+        *stored = warnings.iter().flatten().filter_map(|w| CString::new(w.as_str()).ok()).collect();
+        stored.len()
+    });
+
+    match (warnings, stored) {
+        (Some(_), Some(count)) => {
+            set_status(ProcessStatus::Success, CommandType::None);
+            count as c_uint
+        }
+        _ => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            0
+        }
+    }
+}
+
+// The string is owned by the engine and stays valid until the next validate_chip_song
+#[unsafe(no_mangle)]
+pub extern "C" fn get_chip_warning(index: c_uint) -> *const c_char {
+    match CHIP_WARNINGS.lock() {
+        Ok(warnings) => warnings.get(index as usize).map_or(std::ptr::null(), |w| w.as_ptr()),
+        Err(_) => std::ptr::null(),
+    }
+}
+
+
+#[unsafe(no_mangle)]
+pub extern "C" fn set_raw_pcm_options(
+    big_endian: c_uchar,
+    signed: c_uchar,
+    bits_per_sample: c_uint,
+) {
+    set_status(ProcessStatus::InProgress, CommandType::SetRawPcmOptions);
+
+    if !matches!(bits_per_sample, 8 | 16 | 24 | 32) {
+        set_status(ProcessStatus::Error, CommandType::None);
+        return;
+    }
+
+    RAW_BIG_ENDIAN.store(big_endian == 1, Ordering::SeqCst);
+    RAW_SIGNED.store(signed == 1, Ordering::SeqCst);
+    RAW_BITS_PER_SAMPLE.store(bits_per_sample, Ordering::SeqCst);
+
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+
+fn update_wav_metadata(update: impl FnOnce(&mut WavMetadata)) {
+    set_status(ProcessStatus::InProgress, CommandType::SetWavMetadata);
+
+    match WAV_METADATA.lock() {
+        Ok(mut metadata) => {
+            update(&mut metadata);
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn set_wav_info(c_title: *const c_char, c_artist: *const c_char) {
+    let title: String = c_char_to_string(c_title).unwrap_or_default();
+    let artist: String = c_char_to_string(c_artist).unwrap_or_default();
+
+    update_wav_metadata(|metadata| {
+        metadata.title = title;
+        metadata.artist = artist;
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn set_wav_loop_points(start_milliseconds: c_uint, end_milliseconds: c_uint) {
+    update_wav_metadata(|metadata| {
+        metadata.loop_points = if end_milliseconds > start_milliseconds {
+            Some((start_milliseconds, end_milliseconds))
+        } else {
+            None
+        };
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn add_wav_cue_marker(milliseconds: c_uint, c_label: *const c_char) {
+    let label: String = c_char_to_string(c_label).unwrap_or_default();
+
+    update_wav_metadata(|metadata| {
+        metadata.cue_markers.push(CueMarker { milliseconds, label });
+    });
+}
+
+// cue markers where each channel starts and stops sounding and where the song resumes after a rest
+#[unsafe(no_mangle)]
+pub extern "C" fn set_wav_boundary_markers(enabled: c_uchar) {
+    update_wav_metadata(|metadata| metadata.boundary_markers = enabled == 1);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_wav_metadata() {
+    update_wav_metadata(|metadata| *metadata = WavMetadata::new());
+}
+
 
 #[unsafe(no_mangle)]
 pub extern "C" fn set_sample_rate(new_sample_rate: c_uint) {
     set_status(ProcessStatus::InProgress,CommandType::SetSampleRate);
+    if !is_valid_sample_rate(new_sample_rate) {
+        set_status(ProcessStatus::Error, CommandType::None);
+        return;
+    }
     SAMPLE_RATE.store(new_sample_rate, Ordering::SeqCst);
     set_status(ProcessStatus::Success,CommandType::None);   
 }
@@ -53,99 +271,813 @@ pub extern "C" fn get_sample_rate() -> c_uint{
 
 // ------------------------------------------------------------------------------
 
+fn push_channel_effect(channel_index: c_uint, settings: EffectSettings) -> bool {
+    let mut channel_effects = match CHANNEL_EFFECTS.lock() {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+
+    let index: usize = channel_index as usize;
+    if channel_effects.len() <= index {
+        channel_effects.resize_with(index + 1, Vec::new);
+    }
+    channel_effects[index].push(settings);
+
+    true
+}
+
 #[unsafe(no_mangle)]
-pub extern "C" fn synthesize_audio(
+pub extern "C" fn add_channel_modulation_effect(
+    channel_index: c_uint,
+    effect_type: c_int,
+    rate: c_float,
+    depth: c_float,
+    feedback: c_float,
+    mix: c_float,
+) {
+    set_status(ProcessStatus::InProgress, CommandType::AddChannelEffect);
+
+    let modulation = ModulationSettings { rate, depth, feedback, mix };
+
+    let settings: EffectSettings = match EffectType::from_i32(effect_type) {
+        Some(EffectType::Chorus) => EffectSettings::Chorus(modulation),
+        Some(EffectType::Flanger) => EffectSettings::Flanger(modulation),
+        Some(EffectType::Phaser) => EffectSettings::Phaser(modulation),
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    set_status(
+        if push_channel_effect(channel_index, settings) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn add_channel_waveshaper(
+    channel_index: c_uint,
+    shape: c_int,
+    drive: c_float,
+    output_gain: c_float,
+    oversampling: c_uint,
+) {
+    set_status(ProcessStatus::InProgress, CommandType::AddChannelWaveshaper);
+
+    let shape: WaveshaperShape = match WaveshaperShape::from_i32(shape) {
+        Some(s) => s,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    let settings = EffectSettings::Waveshaper(WaveshaperSettings {
+        shape,
+        drive,
+        output_gain,
+        oversampling,
+    });
+
+    set_status(
+        if push_channel_effect(channel_index, settings) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_channel_effects(channel_index: c_uint) {
+    set_status(ProcessStatus::InProgress, CommandType::ClearChannelEffects);
+
+    let mut channel_effects = match CHANNEL_EFFECTS.lock() {
+        Ok(o) => o,
+        Err(_) => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    if let Some(chain) = channel_effects.get_mut(channel_index as usize) {
+        chain.clear();
+    }
+
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+
+// ------------------------------------------------------------------------------
+
+fn start_export(
+    job_id: u32,
+    progress: Arc<Progress>,
+    all_notes: Vec<Vec<String>>,
+    output_path: String,
+    config: RenderConfig
+) {
+    if let Some(error) = format_error(&output_path, config.container_format, config.export_format) {
+        end_job(job_id, ProcessStatus::Error, Some(error));
+        return;
+    }
+
+    thread::spawn(move || {
+        let song: RenderedSong = match render_song(&all_notes, &config, &progress) {
+            Some(s) => s,
+            None if progress.is_cancelled() => {
+                end_job(job_id, ProcessStatus::Cancelled, None);
+                return;
+            }
+            None => {
+                end_job(job_id, ProcessStatus::Error, Some("song could not be rendered"));
+                return;
+            }
+        };
+
+        if write_audio(output_path.clone(), song.samples, &song.boundaries, &config, &progress) {
+            end_job(job_id, ProcessStatus::Success, None);
+        } else if progress.is_cancelled() {
+            // a cancelled export must not leave a truncated file behind
+            let _ = fs::remove_file(&output_path);
+            end_job(job_id, ProcessStatus::Cancelled, None);
+        } else {
+            end_job(job_id, ProcessStatus::Error, Some("output file could not be written"));
+        }
+    });
+}
+
+fn spawn_synthesis(
     data: *const *const *const c_char,
     sizes_array: *const c_uint,
     outer_size: c_uint,
     c_str_output_path: *const c_char,
-) {
-    set_status(ProcessStatus::InProgress, CommandType::SynthesizeAudio);
+    config: Option<RenderConfig>,
+    command: CommandType
+) -> c_uint {
+    let (job_id, progress) = begin_job(command);
+    LAST_SYNTHESIS_JOB.store(job_id, Ordering::SeqCst);
 
     let output_path = match c_char_to_string(c_str_output_path) {
         Some(s) => s,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid output path"));
+            return job_id;
+        }
+    };
+
+    let all_notes: Vec<Vec<String>> = match c_song_to_vec(data, sizes_array, outer_size) {
+        Some(n) => n,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid song data"));
+            return job_id;
+        }
+    };
+
+    let config: RenderConfig = match config {
+        Some(c) => c,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid render settings"));
+            return job_id;
+        }
+    };
+
+    start_export(job_id, progress, all_notes, output_path, config);
+    job_id
+}
+
+// Returns the job id, the status of the legacy pair mirrors the latest job
+#[unsafe(no_mangle)]
+pub extern "C" fn synthesize_audio(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+    c_str_output_path: *const c_char,
+) -> c_uint {
+    spawn_synthesis(
+        data,
+        sizes_array,
+        outer_size,
+        c_str_output_path,
+        RenderConfig::from_globals(),
+        CommandType::SynthesizeAudio,
+    )
+}
+
+// Same as synthesize_audio, but the given settings override the global ones for this job only
+#[unsafe(no_mangle)]
+pub extern "C" fn synthesize_audio_with_config(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+    c_str_output_path: *const c_char,
+    c_sample_rate: c_uint,
+    c_export_format: c_int,
+    c_container_format: c_int,
+    c_dither_mode: c_int
+) -> c_uint {
+    let config: Option<RenderConfig> = RenderConfig::from_globals().and_then(|mut config| {
+        if !is_valid_sample_rate(c_sample_rate) {
+            return None;
+        }
+        config.sample_rate = c_sample_rate;
+        config.export_format = ExportFormat::from_i32(c_export_format)?;
+        config.container_format = ContainerFormat::from_i32(c_container_format)?;
+        config.dither_mode = DitherMode::from_i32(c_dither_mode)?;
+        Some(config)
+    });
+
+    spawn_synthesis(
+        data,
+        sizes_array,
+        outer_size,
+        c_str_output_path,
+        config,
+        CommandType::SynthesizeWithConfig,
+    )
+}
+
+
+// ------------------------------------------------------------------------------
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_job_status(job_id: c_uint) -> c_int {
+    job_status(job_id) as c_int
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_job_command(job_id: c_uint) -> c_int {
+    job_command(job_id) as c_int
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_job_progress(job_id: c_uint) -> c_float {
+    job_progress(job_id)
+}
+
+// Null when the job has no error, the string is owned by the engine until release_job
+#[unsafe(no_mangle)]
+pub extern "C" fn get_job_error(job_id: c_uint) -> *const c_char {
+    job_error(job_id)
+}
+
+// The job notices the request at its next progress update and reports Cancelled
+#[unsafe(no_mangle)]
+pub extern "C" fn cancel_job(job_id: c_uint) -> c_uchar {
+    jobs::cancel_job(job_id) as c_uchar
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn release_job(job_id: c_uint) -> c_uchar {
+    jobs::release_job(job_id) as c_uchar
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_active_job_count() -> c_uint {
+    active_job_count()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_synthesis_progress() -> c_float {
+    job_progress(LAST_SYNTHESIS_JOB.load(Ordering::SeqCst))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn cancel_synthesis() {
+    jobs::cancel_job(LAST_SYNTHESIS_JOB.load(Ordering::SeqCst));
+}
+
+
+// ------------------------------------------------------------------------------
+
+#[unsafe(no_mangle)]
+pub extern "C" fn synthesize_audio_to_buffer(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+) -> c_uint {
+    let (job_id, progress) = begin_job(CommandType::SynthesizeToBuffer);
+    LAST_SYNTHESIS_JOB.store(job_id, Ordering::SeqCst);
+
+    let all_notes: Vec<Vec<String>> = match c_song_to_vec(data, sizes_array, outer_size) {
+        Some(n) => n,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid song data"));
+            return job_id;
+        }
+    };
+
+    let config: RenderConfig = match RenderConfig::from_globals() {
+        Some(c) => c,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid render settings"));
+            return job_id;
+        }
+    };
+
+    // the caller may still be reading the previous buffer, it has to be freed first,
+    // the slot is reserved under the same lock so overlapping calls cannot both pass
+    match RENDER_BUFFER.lock() {
+        Ok(mut slot) if matches!(*slot, RenderBufferSlot::Empty) => *slot = RenderBufferSlot::Pending(job_id),
+        _ => {
+            end_job(job_id, ProcessStatus::Error, Some("previous render buffer was not freed"));
+            return job_id;
+        }
+    }
+
+    thread::spawn(move || {
+        let song: Option<RenderedSong> = render_song(&all_notes, &config, &progress);
+
+        let Ok(mut slot) = RENDER_BUFFER.lock() else {
+            end_job(job_id, ProcessStatus::Error, Some("render buffer unavailable"));
+            return;
+        };
+
+        // a free_render_buffer call during the render gives the slot up, the result is dropped
+        if !matches!(*slot, RenderBufferSlot::Pending(id) if id == job_id) {
+            drop(slot);
+            end_job(job_id, ProcessStatus::Cancelled, None);
+            return;
+        }
+
+        match song {
+            Some(song) => {
+                *slot = RenderBufferSlot::Ready(RenderBuffer {
+                    samples: song.samples,
+                    sample_rate: config.sample_rate,
+                    channel_count: 1,
+                });
+                drop(slot);
+                end_job(job_id, ProcessStatus::Success, None);
+            }
+            None => {
+                *slot = RenderBufferSlot::Empty;
+                drop(slot);
+                if progress.is_cancelled() {
+                    end_job(job_id, ProcessStatus::Cancelled, None);
+                } else {
+                    end_job(job_id, ProcessStatus::Error, Some("song could not be rendered"));
+                }
+            }
+        }
+    });
+
+    job_id
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_render_buffer_pointer() -> *const c_float {
+    match RENDER_BUFFER.lock() {
+        Ok(slot) => match slot.ready() {
+            Some(b) => b.samples.as_ptr(),
+            None => std::ptr::null(),
+        },
+        Err(_) => std::ptr::null(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_render_buffer_length() -> c_uint {
+    match RENDER_BUFFER.lock() {
+        Ok(slot) => slot.ready().map_or(0, |b| b.samples.len() as c_uint),
+        Err(_) => 0,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_render_buffer_sample_rate() -> c_uint {
+    match RENDER_BUFFER.lock() {
+        Ok(slot) => slot.ready().map_or(0, |b| b.sample_rate),
+        Err(_) => 0,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_render_buffer_channel_count() -> c_uint {
+    match RENDER_BUFFER.lock() {
+        Ok(slot) => slot.ready().map_or(0, |b| b.channel_count),
+        Err(_) => 0,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn free_render_buffer() {
+    set_status(ProcessStatus::InProgress, CommandType::FreeRenderBuffer);
+
+    match RENDER_BUFFER.lock() {
+        Ok(mut slot) => {
+            *slot = RenderBufferSlot::Empty;
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+
+// ------------------------------------------------------------------------------
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_render_cache() {
+    set_status(ProcessStatus::InProgress, CommandType::ClearRenderCache);
+    clear_row_cache();
+    set_status(ProcessStatus::Success, CommandType::None);
+}
+
+
+// ------------------------------------------------------------------------------
+
+// Loads and migrates a project, then applies its render settings and effects to the engine
+#[unsafe(no_mangle)]
+pub extern "C" fn load_project(c_str_path: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::LoadProject);
+
+    let project: Project = match c_char_to_string(c_str_path).and_then(|p| Project::load(&p)) {
+        Some(p) => p,
         None => {
             set_status(ProcessStatus::Error, CommandType::None);
             return;
         }
     };
 
-    if data.is_null() || sizes_array.is_null() || outer_size == 0 {
+    let loaded: LoadedProject = match LoadedProject::new(project) {
+        Some(l) => l,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    if !loaded.project.apply_to_globals() {
         set_status(ProcessStatus::Error, CommandType::None);
         return;
     }
 
-    let sizes: Vec<usize> = unsafe {
-        std::slice::from_raw_parts(sizes_array, outer_size as usize)
-            .iter()
-            .map(|&x| x as usize)
-            .collect()
+    match LOADED_PROJECT.lock() {
+        Ok(mut current) => {
+            *current = Some(loaded);
+            set_status(ProcessStatus::Success, CommandType::None);
+        }
+        Err(_) => set_status(ProcessStatus::Error, CommandType::None),
+    }
+}
+
+// an imported song replaces the loaded project, its rows are read with the project getters
+fn load_imported_song(song: Option<ImportedSong>) -> bool {
+    let mut song: ImportedSong = match song {
+        Some(s) if !s.channels.is_empty() => s,
+        _ => return false,
     };
+    IMPORT_SKIPPED_NOTES.store(song.skipped_notes as u32, Ordering::SeqCst);
+
+    // the project takes its sample files from the bank, so the samples go in first
+    if !song.register_samples() {
+        return false;
+    }
 
+    let Some(config) = RenderConfig::from_globals() else {
+        return false;
+    };
 
-    let mut all_notes: Vec<Vec<String>> = Vec::with_capacity(outer_size as usize);
+    let Some(loaded) = LoadedProject::new(Project::from_imported(song, &config)) else {
+        return false;
+    };
 
-    unsafe {
-        let data_slice = std::slice::from_raw_parts(data, outer_size as usize);
+    if !loaded.project.apply_to_globals() {
+        return false;
+    }
 
-        for (i, &piano_ptr) in data_slice.iter().enumerate() {
-            let inner_size = sizes[i];
-            let notes_slice = std::slice::from_raw_parts(piano_ptr, inner_size);
-            let notes_vec: Vec<String> = notes_slice
-                .iter()
-                .map(|&ptr| c_char_to_string(ptr).unwrap_or_default())
-                .collect();
-            all_notes.push(notes_vec);
+    match LOADED_PROJECT.lock() {
+        Ok(mut current) => {
+            *current = Some(loaded);
+            true
         }
+        Err(_) => false,
     }
+}
 
-    thread::spawn(move || {
-        let mut audio_datas: Vec<Vec<f32>> = Vec::new();
+// Notes of the last successful import that lie outside C0 to B8 and were left out
+#[unsafe(no_mangle)]
+pub extern "C" fn get_import_skipped_note_count() -> c_uint {
+    IMPORT_SKIPPED_NOTES.load(Ordering::SeqCst) as c_uint
+}
 
-        for notes in &all_notes {
-            if notes.is_empty() {
-                set_status(ProcessStatus::Error, CommandType::None);
-                return;
-            }
+#[unsafe(no_mangle)]
+pub extern "C" fn import_midi(c_str_path: *const c_char, c_str_waveform: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::ImportMidi);
 
-            let audio = match generate_channel(notes.clone()) {
-                Some(v) => v,
-                None => {
-                    set_status(ProcessStatus::Error, CommandType::None);
-                    return;
-                }
-            };
-            audio_datas.push(audio);
+    let song: Option<ImportedSong> = match (c_char_to_string(c_str_path), c_char_to_string(c_str_waveform)) {
+        (Some(path), Some(waveform)) => Waveform::from_name(&waveform).and_then(|w| import_midi_file(&path, w)),
+        _ => None,
+    };
+
+    set_status(
+        if load_imported_song(song) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// samples of the module are added to the sample bank and played with SampleN tokens
+#[unsafe(no_mangle)]
+pub extern "C" fn import_mod(c_str_path: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::ImportMod);
+
+    let song: Option<ImportedSong> = c_char_to_string(c_str_path).and_then(|p| import_mod_file(&p));
+
+    set_status(
+        if load_imported_song(song) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// text is MML with channels separated by ';', notes use the waveform unless @n selects one
+#[unsafe(no_mangle)]
+pub extern "C" fn import_mml(c_str_text: *const c_char, c_str_waveform: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::ImportMml);
+
+    let song: Option<ImportedSong> = match (c_char_to_string(c_str_text), c_char_to_string(c_str_waveform)) {
+        (Some(text), Some(waveform)) => Waveform::from_name(&waveform).and_then(|w| parse_mml(&text, w)),
+        _ => None,
+    };
+
+    set_status(
+        if load_imported_song(song) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// the definition is the same JSON object a project stores in "instruments",
+// an instrument with the same name is replaced
+#[unsafe(no_mangle)]
+pub extern "C" fn register_instrument(c_str_json: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::RegisterInstrument);
+
+    let status: bool = c_char_to_string(c_str_json)
+        .and_then(|json| Instrument::from_json(&json))
+        .is_some_and(instrument::register_instrument);
+
+    set_status(
+        if status { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn remove_instrument(c_str_name: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::RemoveInstrument);
+
+    let status: bool = c_char_to_string(c_str_name).is_some_and(|n| instrument::remove_instrument(&n));
+
+    set_status(
+        if status { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_instruments() {
+    set_status(ProcessStatus::InProgress, CommandType::ClearInstruments);
+
+    set_status(
+        if instrument::set_instruments(&[]) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// loads a WAV file into the sample bank and returns its slot for SampleN tokens, -1 on failure
+// without one_shot the sample loops between loop_start and loop_end, a loop_end of 0 means the end
+#[unsafe(no_mangle)]
+pub extern "C" fn load_sample(
+    c_str_path: *const c_char,
+    c_str_root_note: *const c_char,
+    loop_start: c_uint,
+    loop_end: c_uint,
+    one_shot: c_uchar,
+) -> c_int {
+    set_status(ProcessStatus::InProgress, CommandType::LoadSample);
+
+    let file: Option<SampleFile> = match (c_char_to_string(c_str_path), c_char_to_string(c_str_root_note)) {
+        (Some(path), Some(root_note)) => Some(SampleFile {
+            path,
+            root_note,
+            loop_start: loop_start as usize,
+            loop_end: loop_end as usize,
+            one_shot: one_shot != 0,
+            module_sample: None,
+        }),
+        _ => None,
+    };
+
+    match file.and_then(Sample::load).and_then(add_sample) {
+        Some(slot) => {
+            set_status(ProcessStatus::Success, CommandType::None);
+            slot as c_int
         }
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            -1
+        }
+    }
+}
 
-        let max_length = audio_datas.iter().map(|c| c.len()).max().unwrap_or(0);
-        let mut output_data = Vec::with_capacity(max_length);
+// drops every sample, rows using SampleN tokens fail to render until they are loaded again
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_samples() {
+    set_status(ProcessStatus::InProgress, CommandType::ClearSamples);
+    clear_sample_bank();
+    set_status(ProcessStatus::Success, CommandType::None);
+}
 
-        for i in 0..max_length {
-            let mut sum = 0.0;
-            let mut active_channels = 0;
+#[unsafe(no_mangle)]
+pub extern "C" fn get_project_channel_count() -> c_uint {
+    match LOADED_PROJECT.lock() {
+        Ok(current) => current.as_ref().map_or(0, |l| l.row_strings.len() as c_uint),
+        Err(_) => 0,
+    }
+}
 
-            for channel in &audio_datas {
-                if let Some(&sample) = channel.get(i) {
-                    if sample != 0.0 {
-                        sum += sample;
-                        active_channels += 1;
-                    }
-                }
-            }
+#[unsafe(no_mangle)]
+pub extern "C" fn get_project_row_count(channel_index: c_uint) -> c_uint {
+    match LOADED_PROJECT.lock() {
+        Ok(current) => current
+            .as_ref()
+            .and_then(|l| l.row_strings.get(channel_index as usize))
+            .map_or(0, |rows| rows.len() as c_uint),
+        Err(_) => 0,
+    }
+}
+
+// The string is owned by the engine and stays valid until the next load_project
+#[unsafe(no_mangle)]
+pub extern "C" fn get_project_row(channel_index: c_uint, row_index: c_uint) -> *const c_char {
+    match LOADED_PROJECT.lock() {
+        Ok(current) => current
+            .as_ref()
+            .and_then(|l| l.row_strings.get(channel_index as usize))
+            .and_then(|rows| rows.get(row_index as usize))
+            .map_or(std::ptr::null(), |row| row.as_ptr()),
+        Err(_) => std::ptr::null(),
+    }
+}
 
-            output_data.push(if active_channels > 0 {
-                sum / active_channels as f32
-            } else {
-                0.0
-            });
+#[unsafe(no_mangle)]
+pub extern "C" fn get_project_tempo() -> c_float {
+    match LOADED_PROJECT.lock() {
+        Ok(current) => current.as_ref().map_or(0.0, |l| l.project.tempo),
+        Err(_) => 0.0,
+    }
+}
+
+// wraps the song the GUI sends, keeping what only the loaded project knows about
+fn project_from_song(all_notes: &[Vec<String>]) -> Option<Project> {
+    let config: RenderConfig = RenderConfig::from_globals()?;
+
+    let previous: Option<Project> = match LOADED_PROJECT.lock() {
+        Ok(current) => current.as_ref().map(|l| l.project.clone()),
+        Err(_) => None,
+    };
+
+    let mut project: Project = Project::from_song(
+        all_notes,
+        previous.as_ref().map_or(DEFAULT_TEMPO, |p| p.tempo),
+        &config,
+    );
+
+    if let Some(previous) = previous {
+        for (channel, old) in project.channels.iter_mut().zip(previous.channels) {
+            channel.name = old.name;
         }
+    }
 
-        let status: bool = write_wav(output_path, output_data);
+    Some(project)
+}
 
-        set_status(
-            if status { ProcessStatus::Success } else { ProcessStatus::Error },
-            CommandType::None,
-        );
-    });
-}
\ No newline at end of file
+// Saves the song with the current engine settings and instruments, keeping the tempo of the loaded project
+#[unsafe(no_mangle)]
+pub extern "C" fn save_project(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+    c_str_path: *const c_char,
+) {
+    set_status(ProcessStatus::InProgress, CommandType::SaveProject);
+
+    let path: String = match c_char_to_string(c_str_path) {
+        Some(p) => p,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    let all_notes: Vec<Vec<String>> = match c_song_to_vec(data, sizes_array, outer_size) {
+        Some(n) => n,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    let project: Project = match project_from_song(&all_notes) {
+        Some(p) => p,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    set_status(
+        if project.save(&path) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// Writes the song as a type 1 MIDI file, one track per channel
+#[unsafe(no_mangle)]
+pub extern "C" fn export_midi(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+    c_str_path: *const c_char,
+) {
+    set_status(ProcessStatus::InProgress, CommandType::ExportMidi);
+
+    let path: String = match c_char_to_string(c_str_path) {
+        Some(p) => p,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    let project: Project = match c_song_to_vec(data, sizes_array, outer_size).and_then(|n| project_from_song(&n)) {
+        Some(p) => p,
+        None => {
+            set_status(ProcessStatus::Error, CommandType::None);
+            return;
+        }
+    };
+
+    set_status(
+        if write_midi(&path, &project) { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// Rewrites a project file of any older version in the current format
+#[unsafe(no_mangle)]
+pub extern "C" fn migrate_project(c_str_input_path: *const c_char, c_str_output_path: *const c_char) {
+    set_status(ProcessStatus::InProgress, CommandType::MigrateProject);
+
+    let status: bool = match (c_char_to_string(c_str_input_path), c_char_to_string(c_str_output_path)) {
+        (Some(input), Some(output)) => Project::load(&input).is_some_and(|p| p.save(&output)),
+        _ => false,
+    };
+
+    set_status(
+        if status { ProcessStatus::Success } else { ProcessStatus::Error },
+        CommandType::None,
+    );
+}
+
+// Renders a project file with its own settings, instruments and samples, the engine's are left as they are
+#[unsafe(no_mangle)]
+pub extern "C" fn synthesize_project(
+    c_str_project_path: *const c_char,
+    c_str_output_path: *const c_char,
+) -> c_uint {
+    let (job_id, progress) = begin_job(CommandType::SynthesizeProject);
+    LAST_SYNTHESIS_JOB.store(job_id, Ordering::SeqCst);
+
+    let output_path: String = match c_char_to_string(c_str_output_path) {
+        Some(s) => s,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid output path"));
+            return job_id;
+        }
+    };
+
+    let project: Project = match c_char_to_string(c_str_project_path).and_then(|p| Project::load(&p)) {
+        Some(p) => p,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("project could not be loaded"));
+            return job_id;
+        }
+    };
+
+    let mut config: RenderConfig = match project.render_config() {
+        Some(c) => c,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("invalid render settings"));
+            return job_id;
+        }
+    };
+
+    config.sounds = match project.sound_bank() {
+        Some(s) => s,
+        None => {
+            end_job(job_id, ProcessStatus::Error, Some("instruments or samples could not be loaded"));
+            return job_id;
+        }
+    };
+
+    start_export(job_id, progress, project.song(), output_path, config);
+    job_id
+}
@@ -1,8 +1,64 @@
-use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64};
 
-pub static SAMPLE_RATE  : AtomicU32  = AtomicU32::new(44100);
-pub static BIT_8_STATUS : AtomicBool = AtomicBool::new(false);
+use crate::audio::wav_metadata::WavMetadata;
+use crate::common_types::{ChipProfile, ContainerFormat, DitherMode, ExportFormat};
+use crate::effects::EffectSettings;
+use crate::jobs::Job;
+use crate::project::LoadedProject;
+use crate::render::RenderBufferSlot;
+use crate::render_cache::RowKey;
+use crate::synth::instrument::Instrument;
+use crate::synth::sampler::Sample;
+
+pub static SAMPLE_RATE   : AtomicU32 = AtomicU32::new(44100);
+pub static EXPORT_FORMAT : AtomicI32 = AtomicI32::new(ExportFormat::Int16 as i32);
+pub static DITHER_MODE   : AtomicI32 = AtomicI32::new(DitherMode::None as i32);
+pub static CONTAINER_FORMAT : AtomicI32 = AtomicI32::new(ContainerFormat::Auto as i32);
+pub static CHIP_PROFILE  : AtomicI32 = AtomicI32::new(ChipProfile::None as i32);
+
+pub static RAW_BIG_ENDIAN      : AtomicBool = AtomicBool::new(false);
+pub static RAW_SIGNED          : AtomicBool = AtomicBool::new(false);
+pub static RAW_BITS_PER_SAMPLE : AtomicU32  = AtomicU32::new(8);
+
+pub static WAV_METADATA : Mutex<WavMetadata> = Mutex::new(WavMetadata::new());
+
+// engine owned result of synthesize_audio_to_buffer, kept until free_render_buffer
+pub static RENDER_BUFFER : Mutex<RenderBufferSlot> = Mutex::new(RenderBufferSlot::Empty);
+
+// project opened with load_project, its tempo and instruments are kept on save
+pub static LOADED_PROJECT : Mutex<Option<LoadedProject>> = Mutex::new(None);
+
+// generated rows of the last rendered song, reused when their tokens and settings match
+pub static ROW_CACHE : Mutex<BTreeMap<RowKey, Arc<[f32]>>> = Mutex::new(BTreeMap::new());
+
+// notes the last import had to leave out, see ImportedSong
+pub static IMPORT_SKIPPED_NOTES : AtomicU32 = AtomicU32::new(0);
+
+// sample bank referenced by SampleN waveform tokens
+pub static SAMPLES : Mutex<BTreeMap<u16, Arc<Sample>>> = Mutex::new(BTreeMap::new());
+
+// instruments by name, note tokens may name one instead of a waveform
+pub static INSTRUMENTS : Mutex<BTreeMap<String, Arc<Instrument>>> = Mutex::new(BTreeMap::new());
+
+// bumped under the INSTRUMENTS or SAMPLES lock whenever a change can alter a rendered row,
+// cached rows are keyed by it so renders that started earlier cannot store stale audio
+pub static SOUND_GENERATION : AtomicU64 = AtomicU64::new(0);
+
+// messages of the last validate_chip_song call, kept for get_chip_warning
+pub static CHIP_WARNINGS : Mutex<Vec<CString>> = Mutex::new(Vec::new());
+
+// insert chains indexed by channel, applied after each channel is rendered
+pub static CHANNEL_EFFECTS : Mutex<Vec<Vec<EffectSettings>>> = Mutex::new(Vec::new());
+
+// every asynchronous call is tracked here by job id, see jobs.rs
+pub static JOBS : Mutex<BTreeMap<u32, Job>> = Mutex::new(BTreeMap::new());
+pub static NEXT_JOB_ID : AtomicU32 = AtomicU32::new(1);
+// job targeted by the legacy get_synthesis_progress and cancel_synthesis calls
+pub static LAST_SYNTHESIS_JOB : AtomicU32 = AtomicU32::new(0);
 
 
 pub static CURRENT_STATUS : AtomicI32 = AtomicI32::new(0);
-pub static CURRENT_COMMAND : AtomicI32 = AtomicI32::new(0);
\ No newline at end of file
+pub static CURRENT_COMMAND : AtomicI32 = AtomicI32::new(0);
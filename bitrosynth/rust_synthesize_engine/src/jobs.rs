@@ -0,0 +1,205 @@
+use std::ffi::{CString, c_char};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use crate::common_types::ProcessStatus;
+use crate::global_state::{JOBS, NEXT_JOB_ID};
+use crate::progress::Progress;
+
+
+// finished jobs nobody released are dropped oldest first past this count, unless they hold an error
+const MAX_FINISHED_JOBS: usize = 64;
+
+pub struct Job {
+    pub command: i32,
+    pub status: i32,
+    pub error: Option<CString>,
+    pub progress: Arc<Progress>,
+}
+
+impl Job {
+    fn is_finished(&self) -> bool {
+        self.status != ProcessStatus::InProgress as i32
+    }
+
+    // job_error may have handed its string to a caller, so only release_job frees it
+    fn can_prune(&self) -> bool {
+        self.is_finished() && self.error.is_none()
+    }
+}
+
+
+// command and status are stored as plain values so both engines can share the registry
+pub fn start_job(command: i32) -> (u32, Arc<Progress>) {
+    let job_id: u32 = NEXT_JOB_ID.fetch_add(1, Ordering::SeqCst);
+    let progress: Arc<Progress> = Arc::new(Progress::new());
+
+    if let Ok(mut jobs) = JOBS.lock() {
+        let finished: Vec<u32> = jobs
+            .iter()
+            .filter(|(_, job)| job.can_prune())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS)) {
+            jobs.remove(id);
+        }
+
+        jobs.insert(job_id, Job {
+            command,
+            status: ProcessStatus::InProgress as i32,
+            error: None,
+            progress: Arc::clone(&progress),
+        });
+    }
+
+    (job_id, progress)
+}
+
+pub fn finish_job(job_id: u32, status: i32, error: Option<&str>) {
+    if let Ok(mut jobs) = JOBS.lock()
+        && let Some(job) = jobs.get_mut(&job_id)
+    {
+        job.status = status;
+        job.error = error.and_then(|e| CString::new(e).ok());
+    }
+}
+
+// unknown or released ids report an error
+pub fn job_status(job_id: u32) -> i32 {
+    match JOBS.lock() {
+        Ok(jobs) => jobs.get(&job_id).map_or(ProcessStatus::Error as i32, |j| j.status),
+        Err(_) => ProcessStatus::Error as i32,
+    }
+}
+
+pub fn job_command(job_id: u32) -> i32 {
+    match JOBS.lock() {
+        Ok(jobs) => jobs.get(&job_id).map_or(0, |j| j.command),
+        Err(_) => 0,
+    }
+}
+
+pub fn job_progress(job_id: u32) -> f32 {
+    match JOBS.lock() {
+        Ok(jobs) => match jobs.get(&job_id) {
+            Some(job) if job.status == ProcessStatus::Success as i32 => 1.0,
+            Some(job) => job.progress.fraction(),
+            None => 0.0,
+        },
+        Err(_) => 0.0,
+    }
+}
+
+// the string lives in the registry, it stays valid until the job is released
+pub fn job_error(job_id: u32) -> *const c_char {
+    match JOBS.lock() {
+        Ok(jobs) => match jobs.get(&job_id).and_then(|j| j.error.as_ref()) {
+            Some(e) => e.as_ptr(),
+            None => std::ptr::null(),
+        },
+        Err(_) => std::ptr::null(),
+    }
+}
+
+pub fn cancel_job(job_id: u32) -> bool {
+    match JOBS.lock() {
+        Ok(jobs) => match jobs.get(&job_id) {
+            Some(job) if !job.is_finished() => {
+                job.progress.cancel();
+                true
+            }
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+pub fn release_job(job_id: u32) -> bool {
+    match JOBS.lock() {
+        Ok(mut jobs) => jobs.remove(&job_id).is_some(),
+        Err(_) => false,
+    }
+}
+
+pub fn active_job_count() -> u32 {
+    match JOBS.lock() {
+        Ok(jobs) => jobs.values().filter(|j| !j.is_finished()).count() as u32,
+        Err(_) => 0,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    // the pruning test would otherwise drop finished jobs the other tests still look at
+    static REGISTRY: Mutex<()> = Mutex::new(());
+
+    fn lock_registry() -> MutexGuard<'static, ()> {
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn jobs_move_from_in_progress_to_their_final_status() {
+        let _registry: MutexGuard<()> = lock_registry();
+        let (job_id, progress) = start_job(13);
+        assert_eq!(job_status(job_id), ProcessStatus::InProgress as i32);
+        assert_eq!(job_command(job_id), 13);
+        assert!(job_error(job_id).is_null());
+
+        progress.add_work(4);
+        progress.advance(1);
+        assert_eq!(job_progress(job_id), 0.25);
+
+        // success reports full progress even if the last work units were not counted
+        finish_job(job_id, ProcessStatus::Success as i32, None);
+        assert_eq!(job_status(job_id), ProcessStatus::Success as i32);
+        assert_eq!(job_progress(job_id), 1.0);
+
+        assert!(release_job(job_id));
+        assert!(!release_job(job_id));
+        assert_eq!(job_status(job_id), ProcessStatus::Error as i32);
+        assert_eq!(job_command(job_id), 0);
+    }
+
+    #[test]
+    fn only_running_jobs_can_be_cancelled() {
+        let _registry: MutexGuard<()> = lock_registry();
+        let (running, progress) = start_job(1);
+        assert!(cancel_job(running));
+        assert!(progress.is_cancelled());
+        finish_job(running, ProcessStatus::Cancelled as i32, None);
+        assert_eq!(job_status(running), ProcessStatus::Cancelled as i32);
+        assert!(!cancel_job(running));
+
+        let (finished, progress) = start_job(1);
+        finish_job(finished, ProcessStatus::Success as i32, None);
+        assert!(!cancel_job(finished));
+        assert!(!progress.is_cancelled());
+
+        assert!(!cancel_job(u32::MAX));
+        release_job(running);
+        release_job(finished);
+    }
+
+    #[test]
+    fn errors_stay_readable_until_the_job_is_released() {
+        let _registry: MutexGuard<()> = lock_registry();
+        let (failed, _) = start_job(1);
+        finish_job(failed, ProcessStatus::Error as i32, Some("invalid song data"));
+        let error: *const c_char = job_error(failed);
+
+        for _ in 0..MAX_FINISHED_JOBS * 2 {
+            let (job_id, _) = start_job(1);
+            finish_job(job_id, ProcessStatus::Success as i32, None);
+        }
+
+        assert_eq!(job_error(failed), error);
+        assert_eq!(unsafe { std::ffi::CStr::from_ptr(error) }.to_str(), Ok("invalid song data"));
+        assert!(release_job(failed));
+        assert!(job_error(failed).is_null());
+    }
+}
@@ -1,6 +1,17 @@
 pub mod global_state;
 pub mod utils;
+#[cfg(feature = "ffi")]
 pub mod ffi;
 pub mod common_types;
 pub mod synth;
-pub mod audio;
\ No newline at end of file
+pub mod effects;
+pub mod audio;
+pub mod render;
+pub mod render_cache;
+pub mod render_config;
+pub mod project;
+pub mod midi;
+pub mod protracker;
+pub mod mml;
+pub mod progress;
+pub mod jobs;
\ No newline at end of file
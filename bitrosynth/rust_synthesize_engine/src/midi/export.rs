@@ -0,0 +1,215 @@
+use std::fs;
+
+use crate::common_types::Waveform;
+use crate::project::Project;
+use crate::synth::channel::{NoteEvent, parse_row};
+use crate::synth::sound_bank::SoundBank;
+
+use super::frequency_to_midi_note;
+
+
+const TICKS_PER_QUARTER: u16 = 480;
+const PERCUSSION_CHANNEL: u8 = 9;
+const SOFTWARE_NAME: &[u8] = b"BitroSynth";
+
+struct TrackEvent {
+    tick: u64,
+    bytes: Vec<u8>,
+}
+
+
+fn push_variable_length(output: &mut Vec<u8>, value: u64) {
+    let mut groups: Vec<u8> = vec![(value & 0x7F) as u8];
+    let mut rest: u64 = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    output.extend(groups.iter().rev());
+}
+
+fn meta_event(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![0xFF, kind];
+    push_variable_length(&mut bytes, body.len() as u64);
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+fn track_chunk(mut events: Vec<TrackEvent>) -> Vec<u8> {
+    // note offs have to come first among events sharing a tick
+    events.sort_by_key(|e| (e.tick, e.bytes[0] & 0xF0 == 0x90));
+
+    let mut body: Vec<u8> = Vec::new();
+    let mut last_tick: u64 = 0;
+    for event in &events {
+        push_variable_length(&mut body, event.tick - last_tick);
+        body.extend_from_slice(&event.bytes);
+        last_tick = event.tick;
+    }
+    push_variable_length(&mut body, 0);
+    body.extend(meta_event(0x2F, &[]));
+
+    let mut chunk: Vec<u8> = b"MTrk".to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    chunk.extend(body);
+    chunk
+}
+
+// channel 10 is reserved for percussion in General MIDI, melodic channels skip it
+fn midi_channel(channel_index: usize) -> u8 {
+    let channel: u8 = (channel_index % 15) as u8;
+    if channel >= PERCUSSION_CHANNEL { channel + 1 } else { channel }
+}
+
+// General MIDI percussion keys, written on channel 10 whatever channel the drum is in
+fn percussion_key(waveform: Waveform) -> Option<u8> {
+    match waveform {
+        Waveform::Kick => Some(36),
+        Waveform::Snare => Some(38),
+        Waveform::Clap => Some(39),
+        Waveform::ClosedHiHat => Some(42),
+        Waveform::Tom => Some(45),
+        Waveform::OpenHiHat => Some(46),
+        _ => None,
+    }
+}
+
+fn is_pitched(event: &NoteEvent) -> bool {
+    event.gain > 0.0
+        && !event.waveform.is_drum()
+        && !matches!(event.waveform, Waveform::Silence | Waveform::WhiteNoise | Waveform::PinkNoise)
+}
+
+
+pub fn midi_bytes(project: &Project) -> Option<Vec<u8>> {
+    let tempo: f64 = project.tempo.max(1.0) as f64;
+    // samples are not exported, the instruments are enough to read the tokens
+    let sounds: SoundBank = SoundBank::with_instruments(&project.instruments);
+    let microseconds_per_quarter: u32 = (60_000_000.0 / tempo).round() as u32;
+
+    // times are converted from the running total so rounding never accumulates along a row
+    let milliseconds_to_ticks = |milliseconds: u64| -> u64 {
+        (milliseconds as f64 * tempo * TICKS_PER_QUARTER as f64 / 60_000.0).round() as u64
+    };
+
+    let mut tracks: Vec<Vec<u8>> = vec![track_chunk(vec![
+        TrackEvent { tick: 0, bytes: meta_event(0x03, SOFTWARE_NAME) },
+        TrackEvent { tick: 0, bytes: meta_event(0x51, &microseconds_per_quarter.to_be_bytes()[1..]) },
+        TrackEvent { tick: 0, bytes: meta_event(0x58, &[4, 2, 24, 8]) },
+    ])];
+
+    for (channel_index, channel) in project.channels.iter().enumerate() {
+        let status_channel: u8 = midi_channel(channel_index);
+        let mut events: Vec<TrackEvent> = vec![TrackEvent { tick: 0, bytes: meta_event(0x03, channel.name.as_bytes()) }];
+
+        for row in &channel.rows {
+            let mut start: u64 = 0;
+
+            for note in parse_row(row, &sounds)? {
+                let end: u64 = start + note.milliseconds as u64;
+
+                let target: Option<(u8, u8)> = match percussion_key(note.waveform) {
+                    Some(key) if note.gain > 0.0 => Some((PERCUSSION_CHANNEL, key)),
+                    _ if is_pitched(&note) => Some((status_channel, frequency_to_midi_note(note.frequency))),
+                    _ => None,
+                };
+
+                if let Some((midi_channel, key)) = target {
+                    let velocity: u8 = (note.gain.min(1.0) * 127.0).round().max(1.0) as u8;
+
+                    events.push(TrackEvent {
+                        tick: milliseconds_to_ticks(start),
+                        bytes: vec![0x90 | midi_channel, key, velocity],
+                    });
+                    events.push(TrackEvent {
+                        tick: milliseconds_to_ticks(end),
+                        bytes: vec![0x80 | midi_channel, key, 0],
+                    });
+                }
+                start = end;
+            }
+        }
+
+        tracks.push(track_chunk(events));
+    }
+
+    let mut output: Vec<u8> = b"MThd".to_vec();
+    output.extend_from_slice(&6u32.to_be_bytes());
+    output.extend_from_slice(&1u16.to_be_bytes());
+    output.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    output.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    for track in tracks {
+        output.extend(track);
+    }
+
+    Some(output)
+}
+
+pub fn write_midi(path: &str, project: &Project) -> bool {
+    match midi_bytes(project) {
+        Some(bytes) => fs::write(path, bytes).is_ok(),
+        None => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::midi::import::parse_midi;
+    use crate::project::{ChannelDefinition, ImportedSong, PROJECT_VERSION, RenderSettings};
+
+    fn project(tempo: f32, channels: &[(&str, &[&str])]) -> Project {
+        Project {
+            version: PROJECT_VERSION,
+            tempo,
+            instruments: Vec::new(),
+            samples: BTreeMap::new(),
+            render_settings: RenderSettings::default(),
+            channels: channels
+                .iter()
+                .map(|(name, rows)| ChannelDefinition {
+                    name: name.to_string(),
+                    rows: rows.iter().map(|r| r.to_string()).collect(),
+                    effects: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn export_then_import_keeps_the_song() {
+        let rows: [&str; 2] = [
+            "A4_500_1.00_Square>C0_250_0_Silence>C5_250_0.50_Square",
+            "C0_250_0_Silence>E4_750_0.80_Square",
+        ];
+        let bass: [&str; 1] = ["C3_300_1.00_Square>C0_200_0_Silence>G2_500_0.80_Square"];
+        let original: Project = project(90.0, &[("Lead", &rows), ("Bass", &bass)]);
+
+        let song: ImportedSong = parse_midi(&midi_bytes(&original).unwrap(), Waveform::Square).unwrap();
+
+        assert_eq!(song.tempo.round(), 90.0);
+        assert_eq!(song.skipped_notes, 0);
+        assert_eq!(song.channels.len(), 2);
+        assert_eq!(song.channels[0].name, "Lead");
+        assert_eq!(song.channels[0].rows, rows);
+        assert_eq!(song.channels[1].name, "Bass");
+        assert_eq!(song.channels[1].rows, bass);
+    }
+
+    #[test]
+    fn drums_are_written_as_percussion_keys_on_channel_10() {
+        let bytes: Vec<u8> = midi_bytes(&project(120.0, &[("Drums", &["A1_250_1_Kick>G3_250_1_Snare>A4_250_0_ClosedHiHat"])])).unwrap();
+
+        let note_ons: Vec<&[u8]> = bytes.windows(3).filter(|w| w[0] & 0xF0 == 0x90).collect();
+        assert_eq!(note_ons, vec![&[0x99, 36, 127][..], &[0x99, 38, 127][..]]);
+    }
+
+    #[test]
+    fn noise_and_silence_are_left_out() {
+        let bytes: Vec<u8> = midi_bytes(&project(120.0, &[("Noise", &["A4_250_1_WhiteNoise>C0_250_0_Silence"])])).unwrap();
+        assert!(!bytes.iter().any(|b| b & 0xF0 == 0x90));
+    }
+}
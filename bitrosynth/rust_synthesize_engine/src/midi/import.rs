@@ -0,0 +1,445 @@
+use std::fs;
+
+use crate::common_types::Waveform;
+use crate::project::{ImportedChannel, ImportedSong};
+
+use super::{midi_note_frequency, pitch_token, silence_token};
+
+
+const DEFAULT_MICROSECONDS_PER_QUARTER: u32 = 500_000;
+
+struct MidiNote {
+    channel: u8,
+    key: u8,
+    velocity: u8,
+    start: u64,
+    end: u64,
+}
+
+#[derive(Default)]
+struct MidiTrack {
+    name: String,
+    notes: Vec<MidiNote>,
+    tempo_changes: Vec<(u64, u32)>,
+}
+
+enum Division {
+    TicksPerQuarter(u16),
+    Smpte { frames_per_second: u8, ticks_per_frame: u8 },
+}
+
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes: &[u8] = self.data.get(self.position..self.position.checked_add(length)?)?;
+        self.position += length;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    // variable length quantities are at most four bytes of seven bits
+    fn variable_length(&mut self) -> Option<u32> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            let byte: u8 = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+
+fn parse_track(data: &[u8]) -> Option<MidiTrack> {
+    let mut reader: Reader = Reader::new(data);
+    let mut track: MidiTrack = MidiTrack::default();
+    let mut open_notes: Vec<(u8, u8, u8, u64)> = Vec::new();
+    let mut running_status: Option<u8> = None;
+    let mut tick: u64 = 0;
+
+    while !reader.is_empty() {
+        tick += reader.variable_length()? as u64;
+
+        let status: u8 = if reader.peek()? & 0x80 != 0 {
+            reader.u8()?
+        } else {
+            running_status?
+        };
+
+        match status {
+            0xFF => {
+                running_status = None;
+                let kind: u8 = reader.u8()?;
+                let length: usize = reader.variable_length()? as usize;
+                let body: &[u8] = reader.bytes(length)?;
+
+                match kind {
+                    0x03 if track.name.is_empty() => {
+                        track.name = String::from_utf8_lossy(body).trim().to_string();
+                    }
+                    0x51 if length == 3 => {
+                        let tempo: u32 = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                        track.tempo_changes.push((tick, tempo));
+                    }
+                    0x2F => break,
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                running_status = None;
+                let length: usize = reader.variable_length()? as usize;
+                reader.bytes(length)?;
+            }
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let kind: u8 = status & 0xF0;
+                let channel: u8 = status & 0x0F;
+                let key: u8 = reader.u8()?;
+                let velocity: u8 = if kind == 0xC0 || kind == 0xD0 { 0 } else { reader.u8()? };
+
+                let is_note_on: bool = kind == 0x90 && velocity > 0;
+                let is_note_off: bool = kind == 0x80 || (kind == 0x90 && velocity == 0);
+
+                // a retriggered key ends the sounding one, releases match the oldest open note
+                if (is_note_on || is_note_off)
+                    && let Some(index) = open_notes.iter().position(|n| n.0 == channel && n.1 == key)
+                {
+                    let (channel, key, velocity, start) = open_notes.remove(index);
+                    track.notes.push(MidiNote { channel, key, velocity, start, end: tick });
+                }
+                if is_note_on {
+                    open_notes.push((channel, key, velocity, tick));
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    for (channel, key, velocity, start) in open_notes {
+        track.notes.push(MidiNote { channel, key, velocity, start, end: tick });
+    }
+
+    Some(track)
+}
+
+
+struct TempoMap {
+    division: Division,
+    changes: Vec<(u64, u32)>,
+}
+
+impl TempoMap {
+    fn new(division: Division, tracks: &[MidiTrack]) -> TempoMap {
+        let mut changes: Vec<(u64, u32)> = tracks
+            .iter()
+            .flat_map(|t| t.tempo_changes.iter().copied())
+            .collect();
+        changes.sort_by_key(|c| c.0);
+
+        if changes.first().is_none_or(|c| c.0 > 0) {
+            changes.insert(0, (0, DEFAULT_MICROSECONDS_PER_QUARTER));
+        }
+
+        TempoMap { division, changes }
+    }
+
+    fn tick_to_milliseconds(&self, tick: u64) -> f64 {
+        let ticks_per_quarter: f64 = match self.division {
+            Division::TicksPerQuarter(t) => t.max(1) as f64,
+            Division::Smpte { frames_per_second, ticks_per_frame } => {
+                return tick as f64 * 1000.0 / (frames_per_second as f64 * ticks_per_frame.max(1) as f64);
+            }
+        };
+
+        let mut milliseconds: f64 = 0.0;
+        for (index, &(change_tick, tempo)) in self.changes.iter().enumerate() {
+            if change_tick >= tick {
+                break;
+            }
+            let segment_end: u64 = self.changes
+                .get(index + 1)
+                .map_or(tick, |next| next.0.min(tick));
+            milliseconds += (segment_end - change_tick) as f64 * tempo as f64 / 1000.0 / ticks_per_quarter;
+        }
+        milliseconds
+    }
+
+    fn beats_per_minute(&self) -> f32 {
+        60_000_000.0 / self.changes[0].1.max(1) as f32
+    }
+}
+
+
+// greedy row assignment, a note goes to the first row that is free at its start,
+// notes outside C0 to B8 are left out and counted in skipped
+fn notes_to_rows(notes: &[&MidiNote], tempo_map: &TempoMap, waveform: Waveform, skipped: &mut usize) -> Vec<String> {
+    let mut sorted: Vec<&&MidiNote> = notes.iter().collect();
+    sorted.sort_by_key(|n| (n.start, n.key));
+
+    let mut rows: Vec<(u32, Vec<String>)> = Vec::new();
+
+    for note in sorted {
+        let start: u32 = tempo_map.tick_to_milliseconds(note.start).round() as u32;
+        let end: u32 = tempo_map.tick_to_milliseconds(note.end).round() as u32;
+        if end <= start {
+            continue;
+        }
+        let Some(pitch) = pitch_token(midi_note_frequency(note.key as f32)) else {
+            *skipped += 1;
+            continue;
+        };
+
+        let row_index: usize = match rows.iter().position(|r| r.0 <= start) {
+            Some(index) => index,
+            None => {
+                rows.push((0, Vec::new()));
+                rows.len() - 1
+            }
+        };
+
+        let (row_end, tokens) = &mut rows[row_index];
+        if start > *row_end {
+            tokens.push(silence_token(start - *row_end));
+        }
+        tokens.push(format!(
+            "{}_{}_{:.2}_{}",
+            pitch,
+            end - start,
+            note.velocity as f32 / 127.0,
+            waveform.name()
+        ));
+        *row_end = end;
+    }
+
+    rows.into_iter().map(|(_, tokens)| tokens.join(">")).collect()
+}
+
+
+pub fn parse_midi(data: &[u8], waveform: Waveform) -> Option<ImportedSong> {
+    let mut reader: Reader = Reader::new(data);
+
+    if reader.bytes(4)? != b"MThd" {
+        return None;
+    }
+    let header_length: usize = reader.u32()? as usize;
+    let header: &[u8] = reader.bytes(header_length)?;
+    let mut header_reader: Reader = Reader::new(header);
+    let format: u16 = header_reader.u16()?;
+    let track_count: u16 = header_reader.u16()?;
+    let raw_division: u16 = header_reader.u16()?;
+
+    // type 2 files hold independent patterns, they have no single timeline to import
+    if format > 1 {
+        return None;
+    }
+
+    let division: Division = if raw_division & 0x8000 == 0 {
+        Division::TicksPerQuarter(raw_division)
+    } else {
+        Division::Smpte {
+            frames_per_second: (-((raw_division >> 8) as u8 as i8)) as u8,
+            ticks_per_frame: (raw_division & 0xFF) as u8,
+        }
+    };
+
+    let mut tracks: Vec<MidiTrack> = Vec::with_capacity(track_count as usize);
+    while tracks.len() < track_count as usize {
+        let id: &[u8] = reader.bytes(4)?;
+        let length: usize = reader.u32()? as usize;
+        let body: &[u8] = reader.bytes(length)?;
+
+        // unknown chunk types have to be skipped according to the specification
+        if id == b"MTrk" {
+            tracks.push(parse_track(body)?);
+        }
+    }
+
+    let tempo_map: TempoMap = TempoMap::new(division, &tracks);
+    let mut channels: Vec<ImportedChannel> = Vec::new();
+    let mut skipped_notes: usize = 0;
+
+    if format == 0 {
+        // a type 0 file keeps everything in one track, its MIDI channels become our channels
+        let notes: Vec<&MidiNote> = tracks.iter().flat_map(|t| t.notes.iter()).collect();
+        for midi_channel in 0..16u8 {
+            let channel_notes: Vec<&MidiNote> = notes.iter().copied().filter(|n| n.channel == midi_channel).collect();
+            if !channel_notes.is_empty() {
+                channels.push(ImportedChannel {
+                    name: format!("MIDI channel {}", midi_channel + 1),
+                    rows: notes_to_rows(&channel_notes, &tempo_map, waveform, &mut skipped_notes),
+                });
+            }
+        }
+    } else {
+        for (index, track) in tracks.iter().enumerate() {
+            let track_notes: Vec<&MidiNote> = track.notes.iter().collect();
+            if !track_notes.is_empty() {
+                channels.push(ImportedChannel {
+                    name: if track.name.is_empty() { format!("Track {}", index + 1) } else { track.name.clone() },
+                    rows: notes_to_rows(&track_notes, &tempo_map, waveform, &mut skipped_notes),
+                });
+            }
+        }
+    }
+
+    channels.retain(|c| !c.rows.is_empty());
+
+    Some(ImportedSong {
+        tempo: tempo_map.beats_per_minute(),
+        channels,
+        skipped_notes,
+        samples: Vec::new(),
+    })
+}
+
+pub fn import_midi(path: &str, waveform: Waveform) -> Option<ImportedSong> {
+    parse_midi(&fs::read(path).ok()?, waveform)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable_length(value: u32) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![(value & 0x7F) as u8];
+        let mut rest: u32 = value >> 7;
+        while rest > 0 {
+            bytes.insert(0, (rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        bytes
+    }
+
+    // events are (delta ticks, bytes), the end of track is added
+    fn midi_file(format: u16, division: u16, tracks: &[Vec<(u32, Vec<u8>)>]) -> Vec<u8> {
+        let mut data: Vec<u8> = b"MThd".to_vec();
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(&format.to_be_bytes());
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&division.to_be_bytes());
+
+        for events in tracks {
+            let mut body: Vec<u8> = Vec::new();
+            for (delta, bytes) in events {
+                body.extend(variable_length(*delta));
+                body.extend_from_slice(bytes);
+            }
+            body.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend(body);
+        }
+        data
+    }
+
+    #[test]
+    fn type_0_splits_midi_channels_and_reads_running_status() {
+        let data: Vec<u8> = midi_file(0, 480, &[vec![
+            (0, vec![0x90, 69, 127]),
+            (0, vec![0x91, 48, 64]),
+            // running status note off as a note on with velocity 0
+            (480, vec![69, 0]),
+            (0, vec![0x81, 48, 0]),
+        ]]);
+
+        let song: ImportedSong = parse_midi(&data, Waveform::Sine).unwrap();
+        assert_eq!(song.tempo, 120.0);
+        assert_eq!(song.channels.len(), 2);
+        assert_eq!(song.channels[0].name, "MIDI channel 1");
+        assert_eq!(song.channels[0].rows, vec!["A4_500_1.00_Sine"]);
+        assert_eq!(song.channels[1].rows, vec!["C3_500_0.50_Sine"]);
+    }
+
+    #[test]
+    fn overlapping_notes_go_to_separate_rows() {
+        let data: Vec<u8> = midi_file(1, 480, &[vec![
+            (0, vec![0xFF, 0x03, 4, b'L', b'e', b'a', b'd']),
+            (0, vec![0x90, 60, 127]),
+            (240, vec![0x90, 64, 127]),
+            (240, vec![0x80, 60, 0]),
+            (240, vec![0x80, 64, 0]),
+            (240, vec![0x90, 67, 127]),
+            (480, vec![0x80, 67, 0]),
+        ]]);
+
+        let song: ImportedSong = parse_midi(&data, Waveform::Square).unwrap();
+        assert_eq!(song.channels[0].name, "Lead");
+        assert_eq!(song.channels[0].rows, vec![
+            "C4_500_1.00_Square>C0_500_0_Silence>G4_500_1.00_Square",
+            "C0_250_0_Silence>E4_500_1.00_Square",
+        ]);
+    }
+
+    #[test]
+    fn tempo_changes_are_followed() {
+        let data: Vec<u8> = midi_file(1, 480, &[
+            vec![
+                (0, vec![0xFF, 0x51, 3, 0x07, 0xA1, 0x20]),
+                // 60 bpm after the first beat
+                (480, vec![0xFF, 0x51, 3, 0x0F, 0x42, 0x40]),
+            ],
+            vec![(0, vec![0x90, 69, 127]), (960, vec![0x80, 69, 0])],
+        ]);
+
+        let song: ImportedSong = parse_midi(&data, Waveform::Sine).unwrap();
+        assert_eq!(song.tempo, 120.0);
+        assert_eq!(song.channels[0].rows, vec!["A4_1500_1.00_Sine"]);
+    }
+
+    #[test]
+    fn notes_outside_the_engine_range_are_skipped_and_counted() {
+        let data: Vec<u8> = midi_file(0, 480, &[vec![
+            (0, vec![0x90, 0, 100]),
+            (480, vec![0x80, 0, 0]),
+            (0, vec![0x90, 69, 127]),
+            (480, vec![0x80, 69, 0]),
+            (0, vec![0x90, 127, 100]),
+            (480, vec![0x80, 127, 0]),
+        ]]);
+
+        let song: ImportedSong = parse_midi(&data, Waveform::Sine).unwrap();
+        assert_eq!(song.skipped_notes, 2);
+        assert_eq!(song.channels[0].rows, vec!["C0_500_0_Silence>A4_500_1.00_Sine"]);
+    }
+
+    #[test]
+    fn rejects_type_2_and_truncated_files() {
+        let data: Vec<u8> = midi_file(2, 480, &[vec![(0, vec![0x90, 69, 127]), (480, vec![0x80, 69, 0])]]);
+        assert!(parse_midi(&data, Waveform::Sine).is_none());
+
+        let data: Vec<u8> = midi_file(1, 480, &[vec![(0, vec![0x90, 69, 127]), (480, vec![0x80, 69, 0])]]);
+        assert!(parse_midi(&data[..data.len() - 6], Waveform::Sine).is_none());
+    }
+}
@@ -0,0 +1,39 @@
+pub mod export;
+pub mod import;
+
+use crate::utils::note_to_frequency;
+
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// C0, the lowest note note_to_frequency covers, B8 is 107 semitones above it
+const LOWEST_FREQUENCY: f32 = 16.3516;
+const NOTE_COUNT: i32 = 9 * 12;
+
+// equal tempered, A4 = 440 Hz is note 69
+pub fn midi_note_frequency(note: f32) -> f32 {
+    440.0 * 2f32.powf((note - 69.0) / 12.0)
+}
+
+// nearest note name with the cents offset parse_pitch reads, None outside C0 to B8
+pub fn pitch_token(frequency: f32) -> Option<String> {
+    let index: i32 = (12.0 * (frequency / LOWEST_FREQUENCY).log2()).round() as i32;
+    if !(0..NOTE_COUNT).contains(&index) {
+        return None;
+    }
+
+    let name: String = format!("{}{}", NOTE_NAMES[(index % 12) as usize], index / 12);
+    let cents: i32 = (1200.0 * (frequency / note_to_frequency(&name)?).log2()).round() as i32;
+
+    Some(if cents == 0 { name } else { format!("{}{:+}", name, cents) })
+}
+
+// note tokens use the same silence filler as the piano roll
+pub fn silence_token(milliseconds: u32) -> String {
+    format!("C0_{}_0_Silence", milliseconds)
+}
+
+// nearest equal tempered MIDI note, A4 = 440 Hz is note 69
+pub fn frequency_to_midi_note(frequency: f32) -> u8 {
+    (69.0 + 12.0 * (frequency / 440.0).log2()).round().clamp(0.0, 127.0) as u8
+}
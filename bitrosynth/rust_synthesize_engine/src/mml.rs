@@ -0,0 +1,313 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::common_types::Waveform;
+use crate::midi::{midi_note_frequency, pitch_token};
+use crate::project::{DEFAULT_TEMPO, ImportedChannel, ImportedSong};
+
+
+const DEFAULT_OCTAVE: i32 = 4;
+const DEFAULT_LENGTH: u32 = 4;
+const MAX_VOLUME: u32 = 15;
+const DEFAULT_LOOP_COUNT: u32 = 2;
+// nested loops multiply, so both the count and the unrolled channel are capped
+const MAX_LOOP_COUNT: u32 = 256;
+const MAX_EVENTS: usize = 100_000;
+// C0 to B8, the range note_to_frequency covers
+const MAX_OCTAVE: i32 = 8;
+const NOTE_COUNT: i32 = (MAX_OCTAVE + 1) * 12;
+
+// @n selects a waveform in the order they are declared
+const WAVEFORMS: [Waveform; 6] = [
+    Waveform::Triangle,
+    Waveform::Sine,
+    Waveform::Square,
+    Waveform::Sawtooth,
+    Waveform::WhiteNoise,
+    Waveform::PinkNoise,
+];
+
+#[derive(Clone, Copy)]
+struct MmlEvent {
+    note: Option<i32>,
+    milliseconds: f64,
+    gain: f32,
+    waveform: Waveform,
+}
+
+struct ChannelParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    tempo: f64,
+    octave: i32,
+    length: f64,
+    volume: u32,
+    waveform: Waveform,
+    first_tempo: Option<f32>,
+}
+
+impl<'a> ChannelParser<'a> {
+    fn new(text: &'a str, waveform: Waveform, tempo: f32) -> ChannelParser<'a> {
+        ChannelParser {
+            chars: text.chars().peekable(),
+            tempo: tempo as f64,
+            octave: DEFAULT_OCTAVE,
+            length: 1.0 / DEFAULT_LENGTH as f64,
+            volume: MAX_VOLUME,
+            waveform,
+            first_tempo: None,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        self.skip_whitespace();
+        let mut digits: String = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        digits.parse::<u32>().ok()
+    }
+
+    // a length is a note value such as 8 for an eighth, every dot adds half of the previous part
+    fn length(&mut self) -> Option<f64> {
+        let mut length: f64 = match self.number() {
+            Some(0) => return None,
+            Some(n) => 1.0 / n as f64,
+            None => self.length,
+        };
+
+        let mut part: f64 = length;
+        while self.chars.next_if_eq(&'.').is_some() {
+            part /= 2.0;
+            length += part;
+        }
+        Some(length)
+    }
+
+    fn whole_note_milliseconds(&self) -> f64 {
+        4.0 * 60_000.0 / self.tempo
+    }
+
+    fn note(&mut self, letter: char) -> Option<MmlEvent> {
+        let semitone: i32 = match letter {
+            'c' => 0,
+            'd' => 2,
+            'e' => 4,
+            'f' => 5,
+            'g' => 7,
+            'a' => 9,
+            'b' => 11,
+            _ => return None,
+        };
+
+        let mut accidental: i32 = 0;
+        while let Some(c) = self.chars.next_if(|c| matches!(c, '+' | '#' | '-')) {
+            accidental += if c == '-' { -1 } else { 1 };
+        }
+
+        let note: i32 = self.octave * 12 + semitone + accidental;
+        if !(0..NOTE_COUNT).contains(&note) {
+            return None;
+        }
+
+        Some(MmlEvent {
+            note: Some(note),
+            milliseconds: self.length()? * self.whole_note_milliseconds(),
+            gain: self.volume as f32 / MAX_VOLUME as f32,
+            waveform: self.waveform,
+        })
+    }
+
+    // parses until the end of the text or the closing bracket of the loop being read
+    fn sequence(&mut self, in_loop: bool) -> Option<Vec<MmlEvent>> {
+        let mut events: Vec<MmlEvent> = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let Some(command) = self.chars.next() else {
+                return if in_loop { None } else { Some(events) };
+            };
+
+            match command.to_ascii_lowercase() {
+                letter @ 'a'..='g' => events.push(self.note(letter)?),
+                'r' => events.push(MmlEvent {
+                    note: None,
+                    milliseconds: self.length()? * self.whole_note_milliseconds(),
+                    gain: 0.0,
+                    waveform: self.waveform,
+                }),
+                // a tie either extends the last note by a bare length or joins the same pitch
+                '&' => {
+                    self.skip_whitespace();
+                    let extension: MmlEvent = match self.chars.peek().map(|c| c.to_ascii_lowercase()) {
+                        Some('a'..='g') => {
+                            let letter: char = self.chars.next()?.to_ascii_lowercase();
+                            self.note(letter)?
+                        }
+                        _ => MmlEvent {
+                            note: None,
+                            milliseconds: self.length()? * self.whole_note_milliseconds(),
+                            gain: 0.0,
+                            waveform: self.waveform,
+                        },
+                    };
+
+                    match events.last_mut() {
+                        Some(last) if extension.note.is_none() || extension.note == last.note => {
+                            last.milliseconds += extension.milliseconds;
+                        }
+                        Some(_) => events.push(extension),
+                        None => return None,
+                    }
+                }
+                't' => {
+                    let tempo: u32 = self.number().filter(|t| *t > 0)?;
+                    self.tempo = tempo as f64;
+                    self.first_tempo.get_or_insert(tempo as f32);
+                }
+                'o' => self.octave = self.number().filter(|o| *o <= MAX_OCTAVE as u32)? as i32,
+                '>' if self.octave < MAX_OCTAVE => self.octave += 1,
+                '<' if self.octave > 0 => self.octave -= 1,
+                'l' => self.length = self.length()?,
+                'v' => self.volume = self.number()?.min(MAX_VOLUME),
+                '@' => self.waveform = *WAVEFORMS.get(self.number()? as usize)?,
+                '[' => {
+                    let body: Vec<MmlEvent> = self.sequence(true)?;
+                    let count: u32 = self.number().unwrap_or(DEFAULT_LOOP_COUNT);
+                    let total: usize = body.len().checked_mul(count as usize)?.checked_add(events.len())?;
+                    if count > MAX_LOOP_COUNT || total > MAX_EVENTS {
+                        return None;
+                    }
+                    for _ in 0..count {
+                        events.extend_from_slice(&body);
+                    }
+                }
+                ']' if in_loop => return Some(events),
+                _ => return None,
+            }
+        }
+    }
+}
+
+
+// times are rounded from the running total so long songs keep the channels aligned
+fn events_to_row(events: &[MmlEvent]) -> String {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut time: f64 = 0.0;
+
+    for event in events {
+        let start: f64 = time.round();
+        time += event.milliseconds;
+        let milliseconds: u32 = (time.round() - start) as u32;
+        if milliseconds == 0 {
+            continue;
+        }
+
+        // notes are range checked while parsing, so every pitch has a name
+        let pitch: Option<String> = event.note.and_then(|note| pitch_token(midi_note_frequency((note + 12) as f32)));
+        tokens.push(match pitch {
+            Some(pitch) => format!("{}_{}_{:.2}_{}", pitch, milliseconds, event.gain, event.waveform.name()),
+            None => format!("C0_{}_0_Silence", milliseconds),
+        });
+    }
+
+    tokens.join(">")
+}
+
+// channels are separated by ';', every channel becomes a single row and starts
+// at the first tempo set in an earlier channel
+pub fn parse_mml(text: &str, waveform: Waveform) -> Option<ImportedSong> {
+    let mut channels: Vec<ImportedChannel> = Vec::new();
+    let mut tempo: Option<f32> = None;
+
+    for part in text.split(';').filter(|p| !p.trim().is_empty()) {
+        let mut parser: ChannelParser = ChannelParser::new(part, waveform, tempo.unwrap_or(DEFAULT_TEMPO));
+        let events: Vec<MmlEvent> = parser.sequence(false)?;
+        tempo = tempo.or(parser.first_tempo);
+
+        let row: String = events_to_row(&events);
+        if !row.is_empty() {
+            channels.push(ImportedChannel {
+                name: format!("Channel {}", channels.len() + 1),
+                rows: vec![row],
+            });
+        }
+    }
+
+    Some(ImportedSong {
+        tempo: tempo.unwrap_or(DEFAULT_TEMPO),
+        channels,
+        skipped_notes: 0,
+        samples: Vec::new(),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(text: &str) -> Vec<String> {
+        parse_mml(text, Waveform::Square).unwrap().channels.into_iter().flat_map(|c| c.rows).collect()
+    }
+
+    #[test]
+    fn notes_octaves_and_lengths() {
+        assert_eq!(rows("t120 o4 l8 cd>c"), vec![
+            "C4_250_1.00_Square>D4_250_1.00_Square>C5_250_1.00_Square",
+        ]);
+        assert_eq!(rows("c4. d+16 e-2"), vec![
+            "C4_750_1.00_Square>D#4_125_1.00_Square>D#4_1000_1.00_Square",
+        ]);
+    }
+
+    #[test]
+    fn volume_waveform_and_rests() {
+        assert_eq!(rows("v5 @1 a r8 v15 @3 a"), vec![
+            "A4_500_0.33_Sine>C0_250_0_Silence>A4_500_1.00_Sawtooth",
+        ]);
+    }
+
+    #[test]
+    fn ties_extend_or_join() {
+        assert_eq!(rows("c4&8 d4&d4 e4&f4"), vec![
+            "C4_750_1.00_Square>D4_1000_1.00_Square>E4_500_1.00_Square>F4_500_1.00_Square",
+        ]);
+    }
+
+    #[test]
+    fn loops_repeat_and_nest() {
+        assert_eq!(rows("l8 [c d]3"), vec![
+            "C4_250_1.00_Square>D4_250_1.00_Square>C4_250_1.00_Square>D4_250_1.00_Square>C4_250_1.00_Square>D4_250_1.00_Square",
+        ]);
+        assert_eq!(rows("l8 [[c]2 d]"), vec![
+            "C4_250_1.00_Square>C4_250_1.00_Square>D4_250_1.00_Square>C4_250_1.00_Square>C4_250_1.00_Square>D4_250_1.00_Square",
+        ]);
+    }
+
+    #[test]
+    fn channels_share_the_first_tempo() {
+        let song: ImportedSong = parse_mml("t60 c; d", Waveform::Triangle).unwrap();
+        assert_eq!(song.tempo, 60.0);
+        assert_eq!(song.channels[0].rows, vec!["C4_1000_1.00_Triangle"]);
+        assert_eq!(song.channels[1].name, "Channel 2");
+        assert_eq!(song.channels[1].rows, vec!["D4_1000_1.00_Triangle"]);
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        for text in ["c0", "x", "[c", "c]", "o9 c", "o200000000 c", "o8 >c", "o0 <c", "&c", "@9 c", "t0 c"] {
+            assert!(parse_mml(text, Waveform::Square).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_loops() {
+        assert!(parse_mml("[c]4000000000", Waveform::Square).is_none());
+        assert!(parse_mml("[[[c]200]200]200", Waveform::Square).is_none());
+        assert!(parse_mml("[c]256", Waveform::Square).is_some());
+    }
+}
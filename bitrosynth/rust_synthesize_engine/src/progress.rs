@@ -0,0 +1,105 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+
+// samples written per progress update, small enough to keep cancellation responsive
+pub const PROGRESS_BLOCK: usize = 4096;
+
+// work units of one job, shared between the worker thread and the FFI queries
+pub struct Progress {
+    done: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub const fn new() -> Progress {
+        Progress {
+            done: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn add_work(&self, amount: u64) {
+        self.total.fetch_add(amount, Ordering::SeqCst);
+    }
+
+    // returns false once a cancel was requested, callers stop their work at that point
+    pub fn advance(&self, amount: u64) -> bool {
+        self.done.fetch_add(amount, Ordering::SeqCst);
+        !self.is_cancelled()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn fraction(&self) -> f32 {
+        let total: u64 = self.total.load(Ordering::SeqCst);
+        if total == 0 {
+            return 0.0;
+        }
+
+        (self.done.load(Ordering::SeqCst) as f64 / total as f64).min(1.0) as f32
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_song;
+    use crate::render_config::RenderConfig;
+
+    #[test]
+    fn fraction_follows_the_work_done() {
+        let progress: Progress = Progress::new();
+        assert_eq!(progress.fraction(), 0.0);
+
+        progress.add_work(300);
+        assert!(progress.advance(75));
+        assert_eq!(progress.fraction(), 0.25);
+
+        // work added later lowers the fraction instead of overshooting
+        progress.add_work(100);
+        assert!(progress.advance(500));
+        assert_eq!(progress.fraction(), 1.0);
+    }
+
+    #[test]
+    fn advance_reports_a_cancel_request() {
+        let progress: Progress = Progress::new();
+        progress.add_work(10);
+        assert!(progress.advance(1));
+
+        progress.cancel();
+        assert!(progress.is_cancelled());
+        assert!(!progress.advance(1));
+        assert_eq!(progress.fraction(), 0.2);
+    }
+
+    #[test]
+    fn cancelled_renders_stop_without_a_result() {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.sample_rate = 44100;
+        let song: Vec<Vec<String>> = vec![vec![String::from("A4_1000_1_Sine")]];
+
+        let progress: Progress = Progress::new();
+        assert!(render_song(&song, &config, &progress).is_some());
+        assert_eq!(progress.fraction(), 1.0);
+
+        let progress: Progress = Progress::new();
+        progress.cancel();
+        assert!(render_song(&song, &config, &progress).is_none());
+    }
+}
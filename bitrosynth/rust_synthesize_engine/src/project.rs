@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::common_types::{ChipProfile, ContainerFormat, DitherMode, ExportFormat, Waveform};
+use crate::effects::EffectSettings;
+use crate::global_state::*;
+use crate::render_config::RenderConfig;
+use crate::synth::instrument::{Instrument, registered_instruments, set_instruments};
+use crate::synth::sampler::{Sample, SampleFile, add_sample, insert_sample, remove_module_samples, sample_files};
+use crate::synth::sound_bank::SoundBank;
+use crate::utils::is_valid_sample_rate;
+
+
+// bump together with a migrate_vN step whenever the layout changes
+pub const PROJECT_VERSION: u64 = 1;
+
+pub const DEFAULT_TEMPO: f32 = 120.0;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    pub export_format: ExportFormat,
+    pub dither_mode: DitherMode,
+    pub container_format: ContainerFormat,
+    #[serde(default = "default_chip_profile")]
+    pub chip_profile: ChipProfile,
+}
+
+fn default_chip_profile() -> ChipProfile {
+    ChipProfile::None
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            sample_rate: 44100,
+            export_format: ExportFormat::Int16,
+            dither_mode: DitherMode::None,
+            container_format: ContainerFormat::Auto,
+            chip_profile: ChipProfile::None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChannelDefinition {
+    #[serde(default)]
+    pub name: String,
+    pub rows: Vec<String>,
+    #[serde(default)]
+    pub effects: Vec<EffectSettings>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub version: u64,
+    #[serde(default = "default_tempo")]
+    pub tempo: f32,
+    #[serde(default)]
+    pub instruments: Vec<Instrument>,
+    // sample files by bank slot, module samples are stored as the module path and sample number
+    #[serde(default)]
+    pub samples: BTreeMap<u16, SampleFile>,
+    #[serde(default)]
+    pub render_settings: RenderSettings,
+    pub channels: Vec<ChannelDefinition>,
+}
+
+fn default_tempo() -> f32 {
+    DEFAULT_TEMPO
+}
+
+// result of the MIDI and MOD importers, turned into a project with from_imported
+pub struct ImportedChannel {
+    pub name: String,
+    pub rows: Vec<String>,
+}
+
+pub struct ImportedSong {
+    pub tempo: f32,
+    pub channels: Vec<ImportedChannel>,
+    // notes the engine has no pitch for, left out of the rows
+    pub skipped_notes: usize,
+    // samples the rows name as SampleN by their key, not in the sample bank until register_samples
+    pub samples: Vec<(u16, Sample)>,
+}
+
+
+// a project loaded through the FFI, rows are kept as C strings for the getters
+pub struct LoadedProject {
+    pub project: Project,
+    pub row_strings: Vec<Vec<CString>>,
+}
+
+
+// version 0 is the bare channel list the GUI passes to synthesize_audio
+fn migrate_v0(value: Value) -> Option<Value> {
+    let channels: Vec<Value> = value
+        .get("channels")?
+        .as_array()?
+        .iter()
+        .enumerate()
+        .map(|(index, rows)| json!({ "name": format!("Channel {}", index + 1), "rows": rows }))
+        .collect();
+
+    Some(json!({ "version": 1, "channels": channels }))
+}
+
+pub fn migrate(value: Value) -> Option<Value> {
+    let mut value: Value = match value {
+        Value::Array(channels) => json!({ "version": 0, "channels": channels }),
+        other => other,
+    };
+
+    loop {
+        match value.get("version")?.as_u64()? {
+            PROJECT_VERSION => return Some(value),
+            0 => value = migrate_v0(value)?,
+            // written by a newer engine, refuse instead of silently dropping fields
+            _ => return None,
+        }
+    }
+}
+
+
+// points a SampleN token at the bank slot its sample was given
+fn renumber_sample(token: &str, slots: &BTreeMap<u16, u16>) -> String {
+    let mut parts: Vec<String> = token.split('_').map(String::from).collect();
+    if let Some(Waveform::Sample(key)) = parts.get(3).and_then(|p| Waveform::from_name(p))
+        && let Some(slot) = slots.get(&key)
+    {
+        parts[3] = Waveform::Sample(*slot).name();
+    }
+    parts.join("_")
+}
+
+impl ImportedSong {
+    // an import replaces the song, so the module samples of the previous one are released first
+    pub fn register_samples(&mut self) -> bool {
+        remove_module_samples();
+
+        let mut slots: BTreeMap<u16, u16> = BTreeMap::new();
+        for (key, sample) in self.samples.drain(..) {
+            let Some(slot) = add_sample(sample) else {
+                return false;
+            };
+            slots.insert(key, slot);
+        }
+
+        for row in self.channels.iter_mut().flat_map(|c| c.rows.iter_mut()) {
+            *row = row.split('>').map(|token| renumber_sample(token, &slots)).collect::<Vec<String>>().join(">");
+        }
+        true
+    }
+}
+
+
+impl Project {
+    pub fn from_song(all_notes: &[Vec<String>], tempo: f32, config: &RenderConfig) -> Project {
+        Project {
+            version: PROJECT_VERSION,
+            tempo,
+            instruments: registered_instruments(),
+            samples: sample_files().into_iter().collect(),
+            render_settings: RenderSettings {
+                sample_rate: config.sample_rate,
+                export_format: config.export_format,
+                dither_mode: config.dither_mode,
+                container_format: config.container_format,
+                chip_profile: config.chip_profile,
+            },
+            channels: all_notes
+                .iter()
+                .enumerate()
+                .map(|(index, rows)| ChannelDefinition {
+                    name: format!("Channel {}", index + 1),
+                    rows: rows.clone(),
+                    effects: config.channel_effects.get(index).cloned().unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    // imported songs start without effects, they would not fit channels made from tracks
+    pub fn from_imported(song: ImportedSong, config: &RenderConfig) -> Project {
+        let mut project: Project = Project::from_song(&[], song.tempo, config);
+        project.channels = song
+            .channels
+            .into_iter()
+            .map(|c| ChannelDefinition { name: c.name, rows: c.rows, effects: Vec::new() })
+            .collect();
+        project
+    }
+
+    pub fn from_json(text: &str) -> Option<Project> {
+        let value: Value = serde_json::from_str(text).ok()?;
+        serde_json::from_value(migrate(value)?).ok()
+    }
+
+    pub fn to_json(&self) -> Option<String> {
+        // pretty printed with one field per line so projects diff cleanly
+        serde_json::to_string_pretty(self).ok()
+    }
+
+    pub fn load(path: &str) -> Option<Project> {
+        Project::from_json(&fs::read_to_string(path).ok()?)
+    }
+
+    pub fn save(&self, path: &str) -> bool {
+        match self.to_json() {
+            Some(text) => fs::write(path, text + "\n").is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn song(&self) -> Vec<Vec<String>> {
+        self.channels.iter().map(|c| c.rows.clone()).collect()
+    }
+
+    pub fn render_config(&self) -> Option<RenderConfig> {
+        if !is_valid_sample_rate(self.render_settings.sample_rate) {
+            return None;
+        }
+
+        let mut config: RenderConfig = RenderConfig::from_globals()?;
+        config.sample_rate = self.render_settings.sample_rate;
+        config.export_format = self.render_settings.export_format;
+        config.dither_mode = self.render_settings.dither_mode;
+        config.container_format = self.render_settings.container_format;
+        config.chip_profile = self.render_settings.chip_profile;
+        config.channel_effects = self.channels.iter().map(|c| c.effects.clone()).collect();
+        Some(config)
+    }
+
+    // the instruments and samples of the file on their own, for renders that leave the engine's alone
+    pub fn sound_bank(&self) -> Option<SoundBank> {
+        if !self.instruments.iter().all(|i| i.is_valid()) {
+            return None;
+        }
+
+        let mut sounds: SoundBank = SoundBank::with_instruments(&self.instruments);
+        for (slot, file) in &self.samples {
+            sounds.samples.insert(*slot, Arc::new(Sample::load(file.clone())?));
+        }
+        Some(sounds)
+    }
+
+    // instruments and samples the rows name, registered with the engine
+    pub fn load_sounds(&self) -> bool {
+        for (slot, file) in &self.samples {
+            if !Sample::load(file.clone()).is_some_and(|sample| insert_sample(*slot, sample)) {
+                return false;
+            }
+        }
+
+        set_instruments(&self.instruments)
+    }
+
+    pub fn apply_to_globals(&self) -> bool {
+        if !is_valid_sample_rate(self.render_settings.sample_rate) {
+            return false;
+        }
+
+        SAMPLE_RATE.store(self.render_settings.sample_rate, Ordering::SeqCst);
+        EXPORT_FORMAT.store(self.render_settings.export_format as i32, Ordering::SeqCst);
+        DITHER_MODE.store(self.render_settings.dither_mode as i32, Ordering::SeqCst);
+        CONTAINER_FORMAT.store(self.render_settings.container_format as i32, Ordering::SeqCst);
+        CHIP_PROFILE.store(self.render_settings.chip_profile as i32, Ordering::SeqCst);
+
+        if !self.load_sounds() {
+            return false;
+        }
+
+        match CHANNEL_EFFECTS.lock() {
+            Ok(mut effects) => {
+                *effects = self.channels.iter().map(|c| c.effects.clone()).collect();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl LoadedProject {
+    pub fn new(project: Project) -> Option<LoadedProject> {
+        let row_strings: Vec<Vec<CString>> = project
+            .channels
+            .iter()
+            .map(|c| c.rows.iter().map(|r| CString::new(r.as_str()).ok()).collect())
+            .collect::<Option<Vec<Vec<CString>>>>()?;
+
+        Some(LoadedProject { project, row_strings })
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use crate::midi::{midi_note_frequency, pitch_token};
+use crate::project::{ImportedChannel, ImportedSong};
+use crate::synth::sampler::{Sample, SampleFile};
+
+
+const CHANNEL_COUNT: usize = 4;
+const SAMPLE_COUNT: usize = 31;
+const ROWS_PER_PATTERN: usize = 64;
+const PATTERN_SIZE: usize = ROWS_PER_PATTERN * CHANNEL_COUNT * 4;
+const HEADER_SIZE: usize = 1084;
+
+// PAL Amiga, a period of 428 plays the sample at about 8287 Hz and is taken as C4
+const PAL_CLOCK: f32 = 7_093_789.2;
+const REFERENCE_PERIOD: f32 = 428.0;
+const REFERENCE_NOTE: f32 = 60.0;
+const ROOT_NOTE: &str = "C4";
+const MIN_PERIOD: f32 = 113.0;
+const MAX_PERIOD: f32 = 856.0;
+
+const DEFAULT_SPEED: u32 = 6;
+const DEFAULT_TEMPO: u32 = 125;
+
+const VIBRATO_TABLE: [u8; 32] = [
+    0, 24, 49, 74, 97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253,
+    255, 253, 250, 244, 235, 224, 212, 197, 180, 161, 141, 120, 97, 74, 49, 24,
+];
+
+struct SampleHeader {
+    length: usize,
+    finetune: i8,
+    volume: u8,
+    repeat_start: usize,
+    repeat_length: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    sample: usize,
+    period: u16,
+    effect: u8,
+    parameter: u8,
+}
+
+impl Cell {
+    fn new(bytes: &[u8]) -> Cell {
+        Cell {
+            sample: ((bytes[0] & 0xF0) | (bytes[2] >> 4)) as usize,
+            period: (((bytes[0] & 0x0F) as u16) << 8) | bytes[1] as u16,
+            effect: bytes[2] & 0x0F,
+            parameter: bytes[3],
+        }
+    }
+
+    fn high(&self) -> u8 {
+        self.parameter >> 4
+    }
+
+    fn low(&self) -> u8 {
+        self.parameter & 0x0F
+    }
+}
+
+
+#[derive(Default)]
+struct ChannelState {
+    slot: Option<u16>,
+    period: f32,
+    target_period: f32,
+    portamento_speed: u8,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_position: u8,
+    volume: u8,
+    playing: bool,
+    retrigger: bool,
+}
+
+impl ChannelState {
+    fn slide_volume(&mut self, cell: &Cell) {
+        self.volume = if cell.high() > 0 {
+            (self.volume + cell.high()).min(64)
+        } else {
+            self.volume.saturating_sub(cell.low())
+        };
+    }
+
+    fn tone_portamento(&mut self) {
+        let speed: f32 = self.portamento_speed as f32;
+        if self.period < self.target_period {
+            self.period = (self.period + speed).min(self.target_period);
+        } else if self.period > self.target_period {
+            self.period = (self.period - speed).max(self.target_period);
+        }
+    }
+
+    fn vibrato_offset(&self) -> f32 {
+        let value: f32 = VIBRATO_TABLE[(self.vibrato_position & 31) as usize] as f32 * self.vibrato_depth as f32 / 128.0;
+        if self.vibrato_position >= 32 { -value } else { value }
+    }
+
+    fn first_tick(&mut self, cell: &Cell, slots: &[Option<u16>], headers: &[SampleHeader]) {
+        if cell.sample > 0 && cell.sample <= SAMPLE_COUNT {
+            self.slot = slots[cell.sample - 1];
+            self.volume = headers[cell.sample - 1].volume;
+        }
+
+        let is_portamento: bool = cell.effect == 0x3 || cell.effect == 0x5;
+        if cell.period > 0 {
+            if is_portamento {
+                self.target_period = cell.period as f32;
+            } else {
+                self.period = cell.period as f32;
+                self.playing = self.slot.is_some();
+                self.retrigger = true;
+                self.vibrato_position = 0;
+            }
+        }
+
+        match cell.effect {
+            0x3 if cell.parameter > 0 => self.portamento_speed = cell.parameter,
+            0x4 => {
+                if cell.high() > 0 {
+                    self.vibrato_speed = cell.high();
+                }
+                if cell.low() > 0 {
+                    self.vibrato_depth = cell.low();
+                }
+            }
+            0xC => self.volume = cell.parameter.min(64),
+            0xE => match cell.high() {
+                0x1 => self.period = (self.period - cell.low() as f32).max(MIN_PERIOD),
+                0x2 => self.period = (self.period + cell.low() as f32).min(MAX_PERIOD),
+                0xA => self.volume = (self.volume + cell.low()).min(64),
+                0xB => self.volume = self.volume.saturating_sub(cell.low()),
+                0xC if cell.low() == 0 => self.volume = 0,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn later_tick(&mut self, cell: &Cell, tick: u32) {
+        match cell.effect {
+            0x1 => self.period = (self.period - cell.parameter as f32).max(MIN_PERIOD),
+            0x2 => self.period = (self.period + cell.parameter as f32).min(MAX_PERIOD),
+            0x3 => self.tone_portamento(),
+            0x4 => self.vibrato_position = (self.vibrato_position + self.vibrato_speed) & 63,
+            0x5 => {
+                self.tone_portamento();
+                self.slide_volume(cell);
+            }
+            0x6 => {
+                self.vibrato_position = (self.vibrato_position + self.vibrato_speed) & 63;
+                self.slide_volume(cell);
+            }
+            0xA => self.slide_volume(cell),
+            0xE if cell.high() == 0xC && cell.low() as u32 == tick => self.volume = 0,
+            _ => {}
+        }
+    }
+
+    // pitch as a note name with a cents offset, the period is first bent by vibrato and arpeggio
+    fn note(&self, cell: &Cell, tick: u32) -> Option<String> {
+        let mut period: f32 = self.period;
+        if tick > 0 && (cell.effect == 0x4 || cell.effect == 0x6) {
+            period += self.vibrato_offset();
+        }
+
+        let mut note: f32 = REFERENCE_NOTE + 12.0 * (REFERENCE_PERIOD / period.clamp(MIN_PERIOD, MAX_PERIOD)).log2();
+        if cell.effect == 0x0 && cell.parameter > 0 {
+            note += [0, cell.high(), cell.low()][(tick % 3) as usize] as f32;
+        }
+
+        pitch_token(midi_note_frequency(note))
+    }
+}
+
+
+struct Segment {
+    note: Option<(String, u8, u16)>,
+    start: f64,
+    retrigger: bool,
+}
+
+// consecutive ticks with the same pitch and volume are merged into one token
+#[derive(Default)]
+struct RowWriter {
+    tokens: Vec<String>,
+    current: Option<Segment>,
+    carried_retrigger: bool,
+    has_notes: bool,
+}
+
+impl RowWriter {
+    fn flush(&mut self, time: f64) {
+        let Some(segment) = self.current.take() else {
+            return;
+        };
+
+        let milliseconds: u32 = (time.round() - segment.start.round()) as u32;
+        if milliseconds == 0 {
+            self.carried_retrigger |= segment.retrigger;
+            return;
+        }
+
+        let retrigger: bool = segment.retrigger || self.carried_retrigger;
+        self.carried_retrigger = false;
+
+        self.tokens.push(match segment.note {
+            Some((note, volume, slot)) => {
+                self.has_notes = true;
+                format!(
+                    "{}_{}_{:.2}_Sample{}{}",
+                    note,
+                    milliseconds,
+                    volume as f32 / 64.0,
+                    slot,
+                    if retrigger { "" } else { "_Tie" }
+                )
+            }
+            None => format!("C0_{}_0_Silence", milliseconds),
+        });
+    }
+
+    fn write(&mut self, note: Option<(String, u8, u16)>, retrigger: bool, time: f64) {
+        if !retrigger && self.current.as_ref().is_some_and(|s| s.note == note) {
+            return;
+        }
+
+        self.flush(time);
+        self.current = Some(Segment { note, start: time, retrigger });
+    }
+}
+
+
+fn read_u16(data: &[u8], offset: usize) -> usize {
+    u16::from_be_bytes([data[offset], data[offset + 1]]) as usize
+}
+
+fn sample_headers(data: &[u8]) -> Vec<SampleHeader> {
+    (0..SAMPLE_COUNT)
+        .map(|index| {
+            let offset: usize = 20 + index * 30;
+            SampleHeader {
+                length: read_u16(data, offset + 22) * 2,
+                // finetune is a signed nibble in eighths of a semitone
+                finetune: (((data[offset + 24] & 0x0F) << 4) as i8) >> 4,
+                volume: data[offset + 25].min(64),
+                repeat_start: read_u16(data, offset + 26) * 2,
+                repeat_length: read_u16(data, offset + 28) * 2,
+            }
+        })
+        .collect()
+}
+
+// sample headers and the offset of the first sample, None when this is not a four channel module
+fn read_layout(data: &[u8]) -> Option<(Vec<SampleHeader>, usize)> {
+    if data.len() < HEADER_SIZE {
+        return None;
+    }
+
+    // only the four channel ProTracker signatures are read, not 15 sample or multichannel files
+    if !matches!(&data[1080..1084], b"M.K." | b"M!K!" | b"4CHN" | b"FLT4") {
+        return None;
+    }
+
+    let pattern_count: usize = *data[952..1080].iter().max()? as usize + 1;
+    Some((sample_headers(data), HEADER_SIZE + pattern_count * PATTERN_SIZE))
+}
+
+// truncated files are common, the missing part of the last sample is dropped
+fn sample_bytes<'a>(data: &'a [u8], headers: &[SampleHeader], offset: usize, index: usize) -> &'a [u8] {
+    let start: usize = offset + headers[..index].iter().map(|h| h.length).sum::<usize>();
+    let end: usize = (start + headers[index].length).min(data.len());
+    data.get(start..end).unwrap_or_default()
+}
+
+fn sample_data(bytes: &[u8], header: &SampleHeader) -> (Vec<f32>, f32) {
+    (
+        bytes.iter().map(|&b| b as i8 as f32 / 128.0).collect(),
+        PAL_CLOCK / (2.0 * REFERENCE_PERIOD) * 2f32.powf(header.finetune as f32 / 96.0),
+    )
+}
+
+// used by Sample::load to read a module sample a project refers to
+pub fn module_sample_data(path: &str, number: usize) -> Option<(Vec<f32>, f32)> {
+    let data: Vec<u8> = fs::read(path).ok()?;
+    let (headers, offset): (Vec<SampleHeader>, usize) = read_layout(&data)?;
+    let index: usize = number.checked_sub(1).filter(|i| *i < SAMPLE_COUNT)?;
+    Some(sample_data(sample_bytes(&data, &headers, offset, index), &headers[index]))
+}
+
+fn load_samples(data: &[u8], headers: &[SampleHeader], offset: usize, path: &str) -> Vec<Option<Sample>> {
+    (0..SAMPLE_COUNT)
+        .map(|index| {
+            let header: &SampleHeader = &headers[index];
+            let bytes: &[u8] = sample_bytes(data, headers, offset, index);
+            if bytes.len() <= 2 {
+                return None;
+            }
+
+            let loop_end: usize = (header.repeat_start + header.repeat_length).min(bytes.len());
+            let looped: bool = header.repeat_length > 2 && loop_end > header.repeat_start;
+            let (sample, sample_rate): (Vec<f32>, f32) = sample_data(bytes, header);
+
+            Sample::new(sample, sample_rate, SampleFile {
+                path: path.to_string(),
+                root_note: ROOT_NOTE.to_string(),
+                loop_start: if looped { header.repeat_start } else { 0 },
+                loop_end: if looped { loop_end } else { 0 },
+                one_shot: !looped,
+                module_sample: Some(index + 1),
+            })
+        })
+        .collect()
+}
+
+
+// the path is stored with the samples so a saved project can read them again
+pub fn parse_mod(data: &[u8], path: &str) -> Option<ImportedSong> {
+    let (headers, sample_offset): (Vec<SampleHeader>, usize) = read_layout(data)?;
+    let song_length: usize = (data[950] as usize).clamp(1, 128);
+    let orders: &[u8] = &data[952..1080];
+
+    let patterns: &[u8] = data.get(HEADER_SIZE..sample_offset)?;
+    let samples: Vec<Option<Sample>> = load_samples(data, &headers, sample_offset, path);
+
+    // rows name the samples by their index in the module, the load path moves them to bank slots
+    let slots: Vec<Option<u16>> = samples
+        .iter()
+        .enumerate()
+        .map(|(index, s)| s.as_ref().map(|_| index as u16))
+        .collect();
+
+    let mut states: Vec<ChannelState> = (0..CHANNEL_COUNT).map(|_| ChannelState::default()).collect();
+    let mut writers: Vec<RowWriter> = (0..CHANNEL_COUNT).map(|_| RowWriter::default()).collect();
+
+    let mut speed: u32 = DEFAULT_SPEED;
+    let mut tempo: u32 = DEFAULT_TEMPO;
+    let mut first_tempo: Option<f32> = None;
+    let mut time: f64 = 0.0;
+
+    // a position jump back to a played row is the song loop, it ends the import
+    let mut visited: BTreeSet<(usize, usize)> = BTreeSet::new();
+    let (mut order, mut row): (usize, usize) = (0, 0);
+
+    while order < song_length && visited.insert((order, row)) {
+        let row_offset: usize = orders[order] as usize * PATTERN_SIZE + row * CHANNEL_COUNT * 4;
+        let cells: Vec<Cell> = (0..CHANNEL_COUNT)
+            .map(|channel| Cell::new(&patterns[row_offset + channel * 4..row_offset + channel * 4 + 4]))
+            .collect();
+
+        let mut next: (usize, usize) = if row + 1 < ROWS_PER_PATTERN { (order, row + 1) } else { (order + 1, 0) };
+        let mut jumped: bool = false;
+
+        for cell in &cells {
+            match cell.effect {
+                0xF if cell.parameter > 0 && cell.parameter < 32 => speed = cell.parameter as u32,
+                0xF if cell.parameter >= 32 => tempo = cell.parameter as u32,
+                0xB => {
+                    next = (cell.parameter as usize, 0);
+                    jumped = true;
+                }
+                0xD => {
+                    let break_row: usize = (cell.high() * 10 + cell.low()) as usize;
+                    next = (if jumped { next.0 } else { order + 1 }, if break_row < ROWS_PER_PATTERN { break_row } else { 0 });
+                }
+                _ => {}
+            }
+        }
+
+        // with the default speed of 6 the tracker tempo is in quarter notes of four rows
+        first_tempo.get_or_insert(tempo as f32 * DEFAULT_SPEED as f32 / speed as f32);
+        let tick_milliseconds: f64 = 2500.0 / tempo as f64;
+
+        for tick in 0..speed {
+            for ((state, writer), cell) in states.iter_mut().zip(writers.iter_mut()).zip(&cells) {
+                if tick == 0 {
+                    state.first_tick(cell, &slots, &headers);
+                } else {
+                    state.later_tick(cell, tick);
+                }
+
+                let note: Option<(String, u8, u16)> = match state.slot {
+                    Some(slot) if state.playing => state.note(cell, tick).map(|note| (note, state.volume, slot)),
+                    _ => None,
+                };
+                writer.write(note, state.retrigger, time);
+                state.retrigger = false;
+            }
+            time += tick_milliseconds;
+        }
+
+        (order, row) = next;
+    }
+
+    let channels: Vec<ImportedChannel> = writers
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, mut writer)| {
+            writer.flush(time);
+            writer.has_notes.then(|| ImportedChannel {
+                name: format!("Channel {}", index + 1),
+                rows: vec![writer.tokens.join(">")],
+            })
+        })
+        .collect();
+
+    Some(ImportedSong {
+        tempo: first_tempo.unwrap_or(DEFAULT_TEMPO as f32),
+        channels,
+        // periods are clamped to the ProTracker range, which lies inside C0 to B8
+        skipped_notes: 0,
+        samples: samples.into_iter().enumerate().filter_map(|(index, s)| Some((index as u16, s?))).collect(),
+    })
+}
+
+pub fn import_mod(path: &str) -> Option<ImportedSong> {
+    parse_mod(&fs::read(path).ok()?, path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(sample: u8, period: u16, effect: u8, parameter: u8) -> [u8; 4] {
+        [(sample & 0xF0) | (period >> 8) as u8, period as u8, ((sample & 0x0F) << 4) | effect, parameter]
+    }
+
+    // one pattern, sample 1 is a looped square cycle and sample 2 a one shot ramp
+    fn module(cells: &[(usize, usize, [u8; 4])]) -> Vec<u8> {
+        let mut data: Vec<u8> = b"test".to_vec();
+        data.resize(20, 0);
+
+        let samples: [(usize, u8, usize, usize); 2] = [(32, 64, 0, 32), (64, 48, 0, 2)];
+        for index in 0..SAMPLE_COUNT {
+            let (length, volume, repeat_start, repeat_length) = samples.get(index).copied().unwrap_or((0, 0, 0, 2));
+            data.extend_from_slice(&[0; 22]);
+            data.extend_from_slice(&((length / 2) as u16).to_be_bytes());
+            data.extend_from_slice(&[0, volume]);
+            data.extend_from_slice(&((repeat_start / 2) as u16).to_be_bytes());
+            data.extend_from_slice(&((repeat_length / 2) as u16).to_be_bytes());
+        }
+
+        data.extend_from_slice(&[1, 127]);
+        data.extend_from_slice(&[0; 128]);
+        data.extend_from_slice(b"M.K.");
+
+        let mut pattern: Vec<u8> = vec![0; PATTERN_SIZE];
+        for (row, channel, bytes) in cells {
+            let offset: usize = (row * CHANNEL_COUNT + channel) * 4;
+            pattern[offset..offset + 4].copy_from_slice(bytes);
+        }
+        data.extend(pattern);
+
+        data.extend((0..32).map(|i| if i < 16 { 100u8 } else { 156u8 }));
+        data.extend((0..64).map(|i| (i * 2) as u8));
+        data
+    }
+
+    #[test]
+    fn rows_follow_speed_volume_and_pattern_break() {
+        let data: Vec<u8> = module(&[
+            (0, 0, cell(1, 428, 0xF, 3)),
+            (2, 0, cell(0, 0, 0xC, 32)),
+            (0, 1, cell(2, 214, 0x0, 0)),
+            (4, 0, cell(0, 0, 0xD, 0)),
+        ]);
+
+        let song: ImportedSong = parse_mod(&data, "test.mod").unwrap();
+        // speed 3 at 125 bpm is twice the tracker tempo in quarter notes
+        assert_eq!(song.tempo, 250.0);
+        assert_eq!(song.channels.len(), 2);
+
+        assert_eq!(song.channels[0].rows, vec!["C4_120_1.00_Sample0>C4_180_0.50_Sample0_Tie"]);
+        assert_eq!(song.channels[1].rows, vec!["C5_300_0.75_Sample1"]);
+
+        // the samples come back with the song, the rows name them by their index in the module
+        let keys: Vec<u16> = song.samples.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![0, 1]);
+    }
+
+    #[test]
+    fn samples_keep_their_loop_and_source() {
+        let data: Vec<u8> = module(&[]);
+        let (headers, offset): (Vec<SampleHeader>, usize) = read_layout(&data).unwrap();
+        let samples: Vec<Option<Sample>> = load_samples(&data, &headers, offset, "test.mod");
+
+        let square: &Sample = samples[0].as_ref().unwrap();
+        assert_eq!(square.data.len(), 32);
+        assert_eq!(square.data[0], 100.0 / 128.0);
+        assert_eq!(square.data[16], -100.0 / 128.0);
+        assert_eq!(square.loop_range, Some((0, 32)));
+
+        let ramp: &Sample = samples[1].as_ref().unwrap();
+        assert_eq!(ramp.loop_range, None);
+        assert!(samples[2..].iter().all(Option::is_none));
+
+        let source: &SampleFile = ramp.source.as_ref().unwrap();
+        assert_eq!((source.path.as_str(), source.module_sample), ("test.mod", Some(2)));
+    }
+
+    #[test]
+    fn saved_module_samples_load_again() {
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("bitrosynth_{}.mod", std::process::id()));
+        let data: Vec<u8> = module(&[]);
+        fs::write(&path, &data).unwrap();
+
+        let (headers, offset): (Vec<SampleHeader>, usize) = read_layout(&data).unwrap();
+        let imported: Vec<Option<Sample>> = load_samples(&data, &headers, offset, path.to_str().unwrap());
+        let original: &Sample = imported[0].as_ref().unwrap();
+        let loaded: Sample = Sample::load(original.source.clone().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.data, original.data);
+        assert_eq!(loaded.sample_rate, original.sample_rate);
+        assert_eq!(loaded.loop_range, original.loop_range);
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let mut data: Vec<u8> = module(&[]);
+        data[1080..1084].copy_from_slice(b"8CHN");
+        assert!(parse_mod(&data, "test.mod").is_none());
+        assert!(parse_mod(&data[..1000], "test.mod").is_none());
+    }
+}
@@ -0,0 +1,392 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::audio::wav_metadata::CueMarker;
+use crate::common_types::{ChipProfile, Waveform};
+use crate::effects::{EffectSettings, apply_effect_chain, has_waveshaper};
+use crate::progress::Progress;
+use crate::render_cache::{RowKey, cached_rows, store_rows};
+use crate::render_config::RenderConfig;
+use crate::synth::channel::{generate_row, mix_rows, parse_row, sum_rows};
+use crate::synth::chip::{ChipVoice, generate_chip_row, plan_song};
+use crate::synth::sound_bank::SoundBank;
+use crate::utils::milliseconds_to_samples;
+
+
+pub struct RenderedSong {
+    pub samples: Vec<f32>,
+    // cue markers for the export, only filled when the wav metadata asks for them
+    pub boundaries: Vec<CueMarker>,
+}
+
+// a row to render, unless the cache already holds its audio
+struct RowJob<'a> {
+    row: &'a str,
+    chip: Option<ChipVoice>,
+    clip_notes: bool,
+    cached: Option<Arc<[f32]>>,
+}
+
+pub struct RenderBuffer {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channel_count: u32,
+}
+
+// the slot is reserved by a job before it renders, so only that job may fill it
+pub enum RenderBufferSlot {
+    Empty,
+    Pending(u32),
+    Ready(RenderBuffer),
+}
+
+impl RenderBufferSlot {
+    pub fn ready(&self) -> Option<&RenderBuffer> {
+        match self {
+            RenderBufferSlot::Ready(buffer) => Some(buffer),
+            _ => None,
+        }
+    }
+}
+
+
+pub fn mix_channels(audio_datas: &[Vec<f32>]) -> Vec<f32> {
+    let max_length = audio_datas.iter().map(|c| c.len()).max().unwrap_or(0);
+    let mut output_data = Vec::with_capacity(max_length);
+
+    for i in 0..max_length {
+        let mut sum = 0.0;
+        let mut active_channels = 0;
+
+        for channel in audio_datas {
+            if let Some(&sample) = channel.get(i)
+                && sample != 0.0
+            {
+                sum += sample;
+                active_channels += 1;
+            }
+        }
+
+        output_data.push(if active_channels > 0 {
+            sum / active_channels as f32
+        } else {
+            0.0
+        });
+    }
+
+    output_data
+}
+
+// samples generate_channel and the effect chains will process, used as progress total
+fn render_work(
+    all_notes: &[Vec<String>],
+    channel_effects: &[Vec<EffectSettings>],
+    sounds: &SoundBank,
+    sample_rate: f32
+) -> Option<u64> {
+
+    let mut work: u64 = 0;
+
+    for (channel_index, notes) in all_notes.iter().enumerate() {
+        let mut channel_length: u64 = 0;
+
+        for row in notes {
+            let row_length: u64 = parse_row(row, sounds)?
+                .iter()
+                .map(|n| milliseconds_to_samples(n.milliseconds, sample_rate) as u64)
+                .sum();
+            work += row_length;
+            channel_length = channel_length.max(row_length);
+        }
+
+        if channel_effects.get(channel_index).is_some_and(|c| !c.is_empty()) {
+            work += channel_length;
+        }
+    }
+
+    Some(work)
+}
+
+// the milliseconds every note that makes a sound starts and ends at
+fn sounding_spans(row: &str, sounds: &SoundBank) -> Option<Vec<(u32, u32)>> {
+    let mut position: u32 = 0;
+    let mut spans: Vec<(u32, u32)> = Vec::new();
+
+    for note in parse_row(row, sounds)? {
+        let end: u32 = position.saturating_add(note.milliseconds);
+        if note.waveform != Waveform::Silence && note.gain > 0.0 && note.milliseconds > 0 {
+            spans.push((position, end));
+        }
+        position = end;
+    }
+
+    Some(spans)
+}
+
+// where each channel starts and stops sounding, and where sound resumes after a rest in every channel
+pub fn boundary_markers(all_notes: &[Vec<String>], sounds: &SoundBank) -> Option<Vec<CueMarker>> {
+    let mut markers: Vec<CueMarker> = Vec::new();
+    let mut song_spans: Vec<(u32, u32)> = Vec::new();
+
+    for (channel_index, notes) in all_notes.iter().enumerate() {
+        let mut channel_spans: Vec<(u32, u32)> = Vec::new();
+        for row in notes {
+            channel_spans.extend(sounding_spans(row, sounds)?);
+        }
+
+        let start: Option<u32> = channel_spans.iter().map(|s| s.0).min();
+        let end: Option<u32> = channel_spans.iter().map(|s| s.1).max();
+        if let (Some(start), Some(end)) = (start, end) {
+            markers.push(CueMarker { milliseconds: start, label: format!("Channel {} start", channel_index + 1) });
+            markers.push(CueMarker { milliseconds: end, label: format!("Channel {} end", channel_index + 1) });
+        }
+        song_spans.extend(channel_spans);
+    }
+
+    song_spans.sort_unstable();
+    let mut sounding_until: Option<u32> = None;
+    let mut section: usize = 1;
+
+    for (start, end) in song_spans {
+        if sounding_until.is_some_and(|until| start > until) {
+            section += 1;
+            markers.push(CueMarker { milliseconds: start, label: format!("Section {}", section) });
+        }
+        sounding_until = Some(sounding_until.map_or(end, |until| until.max(end)));
+    }
+
+    Some(markers)
+}
+
+// runs task over every item on a pool sized to the machine, results keep the item order
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    task: impl Fn(&T) -> Option<R> + Sync
+) -> Option<Vec<R>> {
+
+    let worker_count: usize = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len());
+
+    if worker_count <= 1 {
+        return items.iter().map(task).collect();
+    }
+
+    let next_item: AtomicUsize = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| loop {
+                let index: usize = next_item.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+
+                let result: Option<R> = task(item);
+                if let Ok(mut slot) = results[index].lock() {
+                    *slot = result;
+                }
+            });
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|slot| slot.into_inner().ok().flatten())
+        .collect()
+}
+
+pub fn render_song(
+    all_notes: &[Vec<String>],
+    config: &RenderConfig,
+    progress: &Progress
+) -> Option<RenderedSong> {
+
+    let sample_rate: f32 = config.sample_rate_f32();
+
+    if all_notes.iter().any(|notes| notes.is_empty()) {
+        return None;
+    }
+
+    // a chip render voices every row on a hardware channel and has no effects to offer
+    let chip_voices: Option<Vec<ChipVoice>> = match config.chip_profile {
+        ChipProfile::None => None,
+        _ => Some(plan_song(all_notes, config)?.voices),
+    };
+    let channel_effects: &[Vec<EffectSettings>] = match chip_voices {
+        Some(_) => &[],
+        None => &config.channel_effects,
+    };
+
+    progress.add_work(render_work(all_notes, channel_effects, &config.sounds, sample_rate)?);
+
+    // rows are independent, so they are spread over the pool before channels are assembled
+    let mut row_jobs: Vec<RowJob> = all_notes
+        .iter()
+        .enumerate()
+        .flat_map(|(channel_index, notes)| {
+            let clip_notes: bool = !channel_effects.get(channel_index).is_some_and(|c| has_waveshaper(c));
+            notes.iter().map(move |row| (row, clip_notes))
+        })
+        .enumerate()
+        .map(|(index, (row, clip_notes))| RowJob {
+            row,
+            chip: chip_voices.as_ref().and_then(|v| v.get(index).copied()),
+            clip_notes,
+            cached: None,
+        })
+        .collect();
+    let row_keys: Vec<Option<RowKey>> = row_jobs
+        .iter()
+        .map(|job| RowKey::new(job.row, config, job.chip, job.clip_notes))
+        .collect();
+    for (job, cached) in row_jobs.iter_mut().zip(cached_rows(&row_keys)) {
+        job.cached = cached;
+    }
+
+    let rendered_rows: Vec<Arc<[f32]>> = parallel_map(&row_jobs, |job| match (&job.cached, job.chip) {
+        (Some(audio), _) => progress.advance(audio.len() as u64).then(|| Arc::clone(audio)),
+        (None, Some(voice)) => generate_chip_row(job.row, voice, config, progress).map(Arc::from),
+        (None, None) => generate_row(job.row, config, job.clip_notes, progress).map(Arc::from),
+    })?;
+
+    store_rows(row_keys, &rendered_rows);
+    let mut row_audios = rendered_rows.into_iter();
+
+    let channels: Vec<(usize, Vec<Arc<[f32]>>)> = all_notes
+        .iter()
+        .enumerate()
+        .map(|(channel_index, notes)| (channel_index, row_audios.by_ref().take(notes.len()).collect()))
+        .collect();
+
+    let audio_datas: Vec<Vec<f32>> = parallel_map(&channels, |(channel_index, channel_rows)| {
+        // a driven channel goes into its waveshaper at full level, the chain clamps the result
+        let mut audio: Vec<f32> = match channel_effects.get(*channel_index) {
+            Some(chain) if has_waveshaper(chain) => sum_rows(channel_rows),
+            _ => mix_rows(channel_rows),
+        };
+
+        if let Some(chain) = channel_effects.get(*channel_index) {
+            apply_effect_chain(&mut audio, chain, sample_rate);
+            if !chain.is_empty() && !progress.advance(audio.len() as u64) {
+                return None;
+            }
+        }
+
+        Some(audio)
+    })?;
+
+    let boundaries: Vec<CueMarker> = if config.wav_metadata.boundary_markers {
+        boundary_markers(all_notes, &config.sounds)?
+    } else {
+        Vec::new()
+    };
+
+    Some(RenderedSong {
+        samples: mix_channels(&audio_datas),
+        boundaries,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::WaveshaperShape;
+    use crate::effects::distortion::WaveshaperSettings;
+
+    fn render(row: &str, chain: Vec<EffectSettings>) -> Vec<f32> {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.sample_rate = 44100;
+        config.chip_profile = ChipProfile::None;
+        config.channel_effects = vec![chain];
+        render_song(&[vec![row.to_string()]], &config, &Progress::new()).unwrap().samples
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |a, b| a.max(b.abs()))
+    }
+
+    #[test]
+    fn parallel_map_keeps_the_item_order() {
+        let items: Vec<u32> = (0..500).collect();
+        let squares: Vec<u32> = parallel_map(&items, |n| Some(n * n)).unwrap();
+        assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<u32>>());
+
+        assert!(parallel_map(&items, |&n| (n != 321).then_some(n)).is_none());
+        assert_eq!(parallel_map(&[] as &[u32], |&n| Some(n)).unwrap(), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn parallel_render_matches_rendering_one_row_at_a_time() {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.sample_rate = 22050;
+        config.chip_profile = ChipProfile::None;
+        config.channel_effects = Vec::new();
+
+        let song: Vec<Vec<String>> = vec![
+            vec![String::from("A4_200_0.8_Square>C5_100_0.5_Triangle"), String::from("E4_300_0.4_Sine")],
+            vec![String::from("C0_50_0_Silence>A2_250_1_Sawtooth")],
+            vec![String::from("A3_120_0.7_Fm2>A1_100_1_Kick"), String::from("G4_80_0.3_Fm4")],
+        ];
+
+        let expected: Vec<Vec<f32>> = song
+            .iter()
+            .map(|rows| {
+                let audios: Vec<Vec<f32>> = rows
+                    .iter()
+                    .map(|row| generate_row(row, &config, true, &Progress::new()).unwrap())
+                    .collect();
+                mix_rows(&audios)
+            })
+            .collect();
+
+        let rendered: Vec<f32> = render_song(&song, &config, &Progress::new()).unwrap().samples;
+        assert_eq!(rendered, mix_channels(&expected));
+    }
+
+    #[test]
+    fn loud_notes_clip_without_turning_down_the_channel() {
+        let samples: Vec<f32> = render("A4_100_2_Square>A4_100_0.5_Square", Vec::new());
+        assert_eq!(peak(&samples[..4410]), 1.0);
+        assert_eq!(peak(&samples[4410..]), 0.5);
+    }
+
+    #[test]
+    fn waveshaper_channels_are_driven_by_the_note_gain() {
+        let soft_clip: EffectSettings = EffectSettings::Waveshaper(WaveshaperSettings {
+            shape: WaveshaperShape::SoftClip,
+            drive: 1.0,
+            output_gain: 1.0,
+            oversampling: 1,
+        });
+
+        let quiet: f32 = peak(&render("A4_100_1_Sine", vec![soft_clip]));
+        let loud: f32 = peak(&render("A4_100_3_Sine", vec![soft_clip]));
+        assert!((quiet - 1f32.tanh()).abs() < 0.01, "{}", quiet);
+        assert!((loud - 3f32.tanh()).abs() < 0.01, "{}", loud);
+    }
+
+    #[test]
+    fn boundaries_follow_the_sounding_notes() {
+        let song: Vec<Vec<String>> = vec![
+            vec![String::from("A4_100_1_Square>C0_200_0_Silence>A4_100_1_Square")],
+            vec![String::from("C0_50_0_Silence>E4_100_1_Sine"), String::from("C4_60_0_Sine>G4_40_1_Sine")],
+        ];
+        let markers: Vec<(u32, String)> = boundary_markers(&song, &SoundBank::default())
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.milliseconds, m.label))
+            .collect();
+
+        assert_eq!(markers, vec![
+            (0, String::from("Channel 1 start")),
+            (400, String::from("Channel 1 end")),
+            (50, String::from("Channel 2 start")),
+            (150, String::from("Channel 2 end")),
+            (300, String::from("Section 2")),
+        ]);
+    }
+}
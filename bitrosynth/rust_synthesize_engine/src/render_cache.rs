@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::common_types::Waveform;
+use crate::global_state::ROW_CACHE;
+use crate::synth::channel::parse_row;
+use crate::render_config::RenderConfig;
+use crate::synth::chip::ChipVoice;
+
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RowKey {
+    sample_rate: u32,
+    generation: u64,
+    chip: Option<ChipVoice>,
+    clip_notes: bool,
+    tokens: String,
+}
+
+impl RowKey {
+    // noise is drawn fresh on every render, so rows using it are never reused,
+    // chip noise comes from a shift register and repeats, sounds from outside
+    // the registry have no generation and are not cached either
+    pub fn new(row: &str, config: &RenderConfig, chip: Option<ChipVoice>, clip_notes: bool) -> Option<RowKey> {
+        let generation: u64 = config.sounds.generation?;
+        let notes = parse_row(row, &config.sounds)?;
+        if chip.is_none() && notes.iter().any(|n| matches!(n.waveform, Waveform::WhiteNoise | Waveform::PinkNoise)) {
+            return None;
+        }
+
+        Some(RowKey { sample_rate: config.sample_rate, generation, chip, clip_notes, tokens: row.trim().to_string() })
+    }
+}
+
+pub fn cached_rows(keys: &[Option<RowKey>]) -> Vec<Option<Arc<[f32]>>> {
+    match ROW_CACHE.lock() {
+        Ok(cache) => keys
+            .iter()
+            .map(|key| key.as_ref().and_then(|k| cache.get(k).cloned()))
+            .collect(),
+        Err(_) => vec![None; keys.len()],
+    }
+}
+
+// only the rows of the latest song are kept, which bounds the cache to one song
+pub fn store_rows(keys: Vec<Option<RowKey>>, rows: &[Arc<[f32]>]) {
+    let new_cache: BTreeMap<RowKey, Arc<[f32]>> = keys
+        .into_iter()
+        .zip(rows)
+        .filter_map(|(key, row)| Some((key?, Arc::clone(row))))
+        .collect();
+
+    if let Ok(mut cache) = ROW_CACHE.lock() {
+        *cache = new_cache;
+    }
+}
+
+pub fn clear_row_cache() {
+    if let Ok(mut cache) = ROW_CACHE.lock() {
+        cache.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::sound_bank::SoundBank;
+
+    fn config(generation: Option<u64>) -> RenderConfig {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.sounds = SoundBank { generation, ..SoundBank::default() };
+        config
+    }
+
+    #[test]
+    fn keys_follow_the_sound_generation() {
+        let row: &str = "A4_100_1_Square>C0_50_0_Silence";
+        let before: Option<RowKey> = RowKey::new(row, &config(Some(3)), None, true);
+        assert!(before.is_some());
+        assert!(before == RowKey::new(row, &config(Some(3)), None, true));
+        assert!(before != RowKey::new(row, &config(Some(4)), None, true));
+    }
+
+    #[test]
+    fn rows_without_a_generation_or_with_noise_are_not_cached() {
+        assert!(RowKey::new("A4_100_1_Square", &config(None), None, true).is_none());
+        assert!(RowKey::new("A4_100_1_WhiteNoise", &config(Some(0)), None, true).is_none());
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::atomic::Ordering;
+
+use crate::audio::wav_metadata::WavMetadata;
+use crate::common_types::{ChipProfile, ContainerFormat, DitherMode, ExportFormat};
+use crate::effects::EffectSettings;
+use crate::global_state::*;
+use crate::synth::sound_bank::SoundBank;
+
+
+// settings of one render, captured when the job starts so later setter calls cannot leak in
+#[derive(Clone)]
+pub struct RenderConfig {
+    pub sample_rate: u32,
+    pub export_format: ExportFormat,
+    pub dither_mode: DitherMode,
+    pub container_format: ContainerFormat,
+    pub raw_big_endian: bool,
+    pub raw_signed: bool,
+    pub raw_bits_per_sample: u16,
+    pub wav_metadata: WavMetadata,
+    pub channel_effects: Vec<Vec<EffectSettings>>,
+    pub chip_profile: ChipProfile,
+    pub sounds: SoundBank,
+}
+
+impl RenderConfig {
+    pub fn from_globals() -> Option<RenderConfig> {
+        Some(RenderConfig {
+            sample_rate: SAMPLE_RATE.load(Ordering::SeqCst),
+            export_format: ExportFormat::from_i32(EXPORT_FORMAT.load(Ordering::SeqCst))
+                .unwrap_or(ExportFormat::Int16),
+            dither_mode: DitherMode::from_i32(DITHER_MODE.load(Ordering::SeqCst))
+                .unwrap_or(DitherMode::None),
+            container_format: ContainerFormat::from_i32(CONTAINER_FORMAT.load(Ordering::SeqCst))
+                .unwrap_or(ContainerFormat::Auto),
+            raw_big_endian: RAW_BIG_ENDIAN.load(Ordering::SeqCst),
+            raw_signed: RAW_SIGNED.load(Ordering::SeqCst),
+            raw_bits_per_sample: RAW_BITS_PER_SAMPLE.load(Ordering::SeqCst) as u16,
+            wav_metadata: WAV_METADATA.lock().ok()?.clone(),
+            channel_effects: CHANNEL_EFFECTS.lock().ok()?.clone(),
+            chip_profile: ChipProfile::from_i32(CHIP_PROFILE.load(Ordering::SeqCst))
+                .unwrap_or(ChipProfile::None),
+            sounds: SoundBank::from_globals()?,
+        })
+    }
+
+    pub fn sample_rate_f32(&self) -> f32 {
+        self.sample_rate as f32
+    }
+}
@@ -1,90 +1,182 @@
-use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-use crate::{global_state::SAMPLE_RATE,utils::note_to_frequency};
+use crate::{common_types::Waveform, effects::apply_effect_chain, progress::Progress, render_config::RenderConfig, utils::{milliseconds_to_samples, note_to_frequency}};
 
-use super::{oscillators::*, noise::*};
+use super::{drums::{DrumSettings, generate_drum}, instrument::{DEFAULT_DUTY, Instrument}, oscillators::*, noise::*, sampler::*, sound_bank::SoundBank};
 
 
-fn generate_silence(milliseconds: u32) -> Vec<f32> {
-    let sample_rate: f32 = SAMPLE_RATE.load(Ordering::SeqCst) as f32;
+fn generate_silence(milliseconds: u32, sample_rate: f32) -> Vec<f32> {
+    vec![0.0; milliseconds_to_samples(milliseconds, sample_rate)]
+}
 
-    return vec![0.0; (milliseconds as f32 / 1000.0 * sample_rate) as usize];
+#[derive(Clone)]
+pub struct NoteEvent {
+    pub frequency: f32,
+    pub milliseconds: u32,
+    pub gain: f32,
+    pub waveform: Waveform,
+    // a tied sample note continues playing from where the previous note stopped
+    pub tie: bool,
+    pub instrument: Option<Arc<Instrument>>,
+    // the sample of a SampleN note, None when the slot is empty
+    pub sample: Option<Arc<Sample>>,
 }
 
-pub fn generate_channel(inputs: Vec<String>) -> Option<Vec<f32>> {
+// an optional cents offset follows the note name, as in A4+25 or C#3-10
+fn parse_pitch(note: &str) -> Option<f32> {
+    let (name, cents) = match note.find(['+', '-']) {
+        Some(index) => (&note[..index], note[index..].parse::<f32>().ok()?),
+        None => (note, 0.0),
+    };
+
+    Some(note_to_frequency(name)? * 2f32.powf(cents / 1200.0))
+}
+
+pub fn parse_note(note_str: &str, sounds: &SoundBank) -> Option<NoteEvent> {
+    let note_parts: Vec<&str> = note_str.split('_').map(|s| s.trim()).collect();
+    if note_parts.len() < 4 {
+        return None;
+    }
+
+    // NOTE name -> frequency
+    let note_name = note_parts[0].to_ascii_uppercase();
+    let frequency: f32 = parse_pitch(&note_name)?;
+
+    // milliseconds (u32)
+    let milliseconds: u32 = match note_parts[1].parse::<u32>() {
+        Ok(v) => v,
+        Err(_) => return None,
+    };
+
+    let gain: f32 = match note_parts[2].replace(',', ".").parse::<f32>() {
+        Ok(v) => v,
+        Err(_) => return None,
+    };
+
+    // the last field names a waveform or a registered instrument
+    let sound: String = note_parts[3].replace(' ', "");
+    let (waveform, instrument): (Waveform, Option<Arc<Instrument>>) = match Waveform::from_name(&sound) {
+        Some(w) => (w, None),
+        None => {
+            let instrument: Arc<Instrument> = sounds.instrument(&sound)?;
+            (instrument.waveform, Some(instrument))
+        }
+    };
+
+    let (frequency, gain): (f32, f32) = match &instrument {
+        Some(i) => (frequency * 2f32.powf(i.detune_cents / 1200.0), gain * i.gain),
+        None => (frequency, gain),
+    };
+
+    let sample: Option<Arc<Sample>> = match waveform {
+        Waveform::Sample(slot) => sounds.sample(slot),
+        _ => None,
+    };
 
-    let mut row_audios: Vec<Vec<f32>> = Vec::with_capacity(inputs.len());
+    let tie: bool = note_parts.get(4).is_some_and(|p| *p == "Tie");
 
-    for (_row_index, input) in inputs.into_iter().enumerate() {
-        let notes: Vec<&str> = input.split('>').collect();
+    Some(NoteEvent { frequency, milliseconds, gain, waveform, tie, instrument, sample })
+}
+
+pub fn parse_row(input: &str, sounds: &SoundBank) -> Option<Vec<NoteEvent>> {
+    input
+        .split('>')
+        .filter(|note_str| !note_str.trim().is_empty())
+        .map(|note_str| parse_note(note_str, sounds))
+        .collect()
+}
 
-        let mut row_wave: Vec<f32> = Vec::new();
+// notes are clipped at full scale unless the channel drives a waveshaper with them
+pub fn generate_row(input: &str, config: &RenderConfig, clip_notes: bool, progress: &Progress) -> Option<Vec<f32>> {
 
-        for note_str in notes.iter() {
-            if note_str.trim().is_empty() { continue; }
+    let sample_rate: f32 = config.sample_rate_f32();
+    let mut row_wave: Vec<f32> = Vec::new();
+    let mut sample_player: Option<(u16, SamplePlayer)> = None;
 
-            let note_parts: Vec<&str> = note_str.split('_').map(|s| s.trim()).collect();
-            if note_parts.len() < 4 {
-                return None;
+    for note in parse_row(input, &config.sounds)? {
+        let milliseconds: u32 = note.milliseconds;
+        let frequency: f32 = note.frequency;
+        let pulse_duty: Option<f32> = note.instrument.as_ref().filter(|i| i.has_pulse_duty()).map(|i| i.duty);
+
+        let mut wave: Vec<f32> = match note.waveform {
+            Waveform::Square if pulse_duty.is_some() => {
+                generate_pulse(milliseconds, frequency, pulse_duty.unwrap_or(DEFAULT_DUTY), sample_rate)
+            }
+            Waveform::Triangle => generate_triangle(milliseconds, frequency, sample_rate),
+            Waveform::Sine => generate_sine(milliseconds, frequency, sample_rate),
+            Waveform::Square => generate_square(milliseconds, frequency, sample_rate),
+            Waveform::Sawtooth => generate_sawtooth(milliseconds, frequency, sample_rate),
+            Waveform::WhiteNoise => generate_noise(milliseconds, sample_rate),
+            Waveform::PinkNoise => generate_pink_noise(milliseconds, sample_rate),
+            Waveform::Silence => generate_silence(milliseconds, sample_rate),
+            Waveform::Kick | Waveform::Snare | Waveform::ClosedHiHat | Waveform::OpenHiHat | Waveform::Tom | Waveform::Clap => {
+                let settings: DrumSettings = note.instrument.as_ref().map_or(DrumSettings::default(), |i| i.drum);
+                generate_drum(note.waveform, milliseconds, frequency, settings, sample_rate)
             }
+            Waveform::Fm2 | Waveform::Fm4 => {
+                let settings: FmSettings = note.instrument.as_ref().map_or(FmSettings::default(), |i| i.fm);
+                generate_fm(note.waveform, settings, milliseconds, frequency, sample_rate)
+            }
+            Waveform::Sample(slot) => {
+                let player: &mut SamplePlayer = match &mut sample_player {
+                    Some((current, player)) if note.tie && *current == slot => player,
+                    _ => &mut sample_player.insert((slot, SamplePlayer::new(note.sample.clone()?))).1,
+                };
+                generate_sample(player, milliseconds, frequency, sample_rate)
+            }
+        };
 
-            // NOTE name -> frequency
-            let note_name = note_parts[0].to_ascii_uppercase();
-            let frequency: f32 = match note_to_frequency(&note_name) {
-                Some(f) => f,
-                None => return None
-            };
-
-            // milliseconds (u32)
-            let milliseconds: u32 = match note_parts[1].parse::<u32>() {
-                Ok(v) => v,
-                Err(_) => return None,
-            };
-
-            let gain: f32 = match note_parts[2].replace(',', ".").parse::<f32>() {
-                Ok(v) => v,
-                Err(_) => return None,
-            };
-
-            let wave_form_type = note_parts[3].replace(' ', "");
-
-            let mut wave: Vec<f32> = match wave_form_type.as_str() {
-                "Triangle" => generate_triangle(milliseconds, frequency),
-                "Sine" => generate_sine(milliseconds, frequency),
-                "Square" => generate_square(milliseconds, frequency),
-                "Sawtooth" => generate_sawtooth(milliseconds, frequency),
-                "WhiteNoise" => generate_noise(milliseconds),
-                "PinkNoise" => generate_pink_noise(milliseconds),
-                "Silence" => generate_silence(milliseconds),
-                _ => return None,
-            };
-
-
-            // apply gain & clamp
-            for sample in wave.iter_mut() {
-                *sample = (*sample * gain).clamp(-1.0, 1.0);
+
+        for sample in wave.iter_mut() {
+            *sample *= note.gain;
+            if clip_notes {
+                *sample = sample.clamp(-1.0, 1.0);
             }
+        }
 
-            row_wave.extend(wave);
-        } // for notes
+        if let Some(instrument) = &note.instrument {
+            if !instrument.envelope.is_flat() {
+                let length: usize = wave.len();
+                for (position, sample) in wave.iter_mut().enumerate() {
+                    *sample *= instrument.envelope.gain_at(position, length, sample_rate);
+                }
+            }
+            if !instrument.effects.is_empty() {
+                apply_effect_chain(&mut wave, &instrument.effects, sample_rate);
+            }
+        }
 
-        row_audios.push(row_wave);
-    } // for rows
+        if !progress.advance(wave.len() as u64) {
+            return None;
+        }
 
-    if row_audios.is_empty() {
-        return Some(Vec::new());
-    }
+        row_wave.extend(wave);
+    } // for notes
 
-    let max_length = row_audios.iter().map(|r| r.len()).max().unwrap_or(0);
+    Some(row_wave)
+}
+
+// rows are summed in order, so the result does not depend on how they were generated
+pub fn sum_rows<R: AsRef<[f32]>>(row_audios: &[R]) -> Vec<f32> {
+
+    let max_length = row_audios.iter().map(|r| r.as_ref().len()).max().unwrap_or(0);
 
     let mut output_data: Vec<f32> = vec![0.0; max_length];
 
-    for row in &row_audios {
-        for (i, &sample) in row.iter().enumerate() {
+    for row in row_audios {
+        for (i, &sample) in row.as_ref().iter().enumerate() {
             output_data[i] += sample;
         }
     }
 
+    output_data
+}
+
+// the sum is scaled down as a whole when it goes past full scale
+pub fn mix_rows<R: AsRef<[f32]>>(row_audios: &[R]) -> Vec<f32> {
+
+    let mut output_data: Vec<f32> = sum_rows(row_audios);
+
     let max_amp = output_data.iter().cloned().fold(0.0f32, |a, b| a.max(b.abs()));
     
     if max_amp > 1.0 {
@@ -93,5 +185,15 @@ pub fn generate_channel(inputs: Vec<String>) -> Option<Vec<f32>> {
         }
     }
 
-    Some(output_data)
-}
\ No newline at end of file
+    output_data
+}
+
+pub fn generate_channel(inputs: Vec<String>, config: &RenderConfig, progress: &Progress) -> Option<Vec<f32>> {
+
+    let row_audios: Vec<Vec<f32>> = inputs
+        .iter()
+        .map(|input| generate_row(input, config, true, progress))
+        .collect::<Option<Vec<Vec<f32>>>>()?;
+
+    Some(mix_rows(&row_audios))
+}
@@ -0,0 +1,578 @@
+use crate::common_types::{ChipProfile, Waveform};
+use crate::progress::Progress;
+use crate::render_config::RenderConfig;
+use crate::utils::milliseconds_to_samples;
+
+use super::channel::{NoteEvent, parse_row};
+use super::instrument::{DEFAULT_DUTY, Envelope};
+use super::oscillators::{oscillator_sample, pulse_sample};
+
+
+// the pitch of a noise note picks the noise clock, A4 lands near 44 kHz
+const NOISE_RATE_PER_HERTZ: f32 = 100.0;
+// notes further than a quarter tone from the nearest playable pitch are reported
+const MAX_TUNING_CENTS: f32 = 50.0;
+// the NES triangle sequencer and the Game Boy wave RAM both hold 32 steps of 4 bits
+const WAVE_TABLE_STEPS: f32 = 32.0;
+const WAVE_TABLE_LEVELS: f32 = 15.0;
+const VOLUME_LEVELS: f32 = 15.0;
+const WAVE_CHANNEL_VOLUMES: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+// the SN76489 attenuates in 2 dB steps, the last step turns the channel off
+const ATTENUATION_STEP_DB: f32 = 2.0;
+const ATTENUATION_STEPS: f32 = 15.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChipChannelKind {
+    Pulse,
+    Triangle,
+    Wave,
+    Noise,
+}
+
+impl ChipChannelKind {
+    fn name(self) -> &'static str {
+        match self {
+            ChipChannelKind::Pulse => "pulse",
+            ChipChannelKind::Triangle => "triangle",
+            ChipChannelKind::Wave => "wave",
+            ChipChannelKind::Noise => "noise",
+        }
+    }
+}
+
+// how a row is voiced in a chip render, rows without a channel are muted
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChipVoice {
+    pub profile: ChipProfile,
+    pub channel: Option<ChipChannelKind>,
+}
+
+// tone frequency = clock / (divider * period), periods outside the range do not fit the registers
+struct ToneTimer {
+    divider: f32,
+    min_period: f32,
+    max_period: f32,
+}
+
+struct ChipSpec {
+    name: &'static str,
+    clock: f32,
+    channels: &'static [ChipChannelKind],
+    duties: &'static [f32],
+    pulse: ToneTimer,
+    triangle: Option<ToneTimer>,
+    wave: Option<ToneTimer>,
+    // noise clock = clock / (divider << shift) for every shift below noise_shifts
+    noise_dividers: &'static [f32],
+    noise_shifts: u32,
+    attenuation_volume: bool,
+}
+
+// NTSC 2A03
+const NES: ChipSpec = ChipSpec {
+    name: "NES",
+    clock: 1_789_773.0,
+    channels: &[ChipChannelKind::Pulse, ChipChannelKind::Pulse, ChipChannelKind::Triangle, ChipChannelKind::Noise],
+    duties: &[0.125, 0.25, 0.5, 0.75],
+    pulse: ToneTimer { divider: 16.0, min_period: 9.0, max_period: 2048.0 },
+    triangle: Some(ToneTimer { divider: 32.0, min_period: 3.0, max_period: 2048.0 }),
+    wave: None,
+    noise_dividers: &[4.0, 8.0, 16.0, 32.0, 64.0, 96.0, 128.0, 160.0, 202.0, 254.0, 380.0, 508.0, 762.0, 1016.0, 2034.0, 4068.0],
+    noise_shifts: 1,
+    attenuation_volume: false,
+};
+
+const GAME_BOY: ChipSpec = ChipSpec {
+    name: "Game Boy",
+    clock: 4_194_304.0,
+    channels: &[ChipChannelKind::Pulse, ChipChannelKind::Pulse, ChipChannelKind::Wave, ChipChannelKind::Noise],
+    duties: &[0.125, 0.25, 0.5, 0.75],
+    pulse: ToneTimer { divider: 32.0, min_period: 1.0, max_period: 2048.0 },
+    triangle: None,
+    wave: Some(ToneTimer { divider: 64.0, min_period: 1.0, max_period: 2048.0 }),
+    noise_dividers: &[8.0, 16.0, 32.0, 48.0, 64.0, 80.0, 96.0, 112.0],
+    noise_shifts: 14,
+    attenuation_volume: false,
+};
+
+// NTSC clock as used in the Master System and the Genesis
+const SN76489: ChipSpec = ChipSpec {
+    name: "SN76489",
+    clock: 3_579_545.0,
+    channels: &[ChipChannelKind::Pulse, ChipChannelKind::Pulse, ChipChannelKind::Pulse, ChipChannelKind::Noise],
+    duties: &[0.5],
+    pulse: ToneTimer { divider: 32.0, min_period: 1.0, max_period: 1023.0 },
+    triangle: None,
+    wave: None,
+    noise_dividers: &[512.0, 1024.0, 2048.0],
+    noise_shifts: 1,
+    attenuation_volume: true,
+};
+
+fn chip_spec(profile: ChipProfile) -> Option<&'static ChipSpec> {
+    match profile {
+        ChipProfile::None => None,
+        ChipProfile::Nes => Some(&NES),
+        ChipProfile::GameBoy => Some(&GAME_BOY),
+        ChipProfile::Sn76489 => Some(&SN76489),
+    }
+}
+
+impl ChipSpec {
+    fn timer(&self, kind: ChipChannelKind) -> Option<&ToneTimer> {
+        match kind {
+            ChipChannelKind::Pulse => Some(&self.pulse),
+            ChipChannelKind::Triangle => self.triangle.as_ref(),
+            ChipChannelKind::Wave => self.wave.as_ref(),
+            ChipChannelKind::Noise => None,
+        }
+    }
+
+    // nearest frequency the period register can hold, None when the note is out of range
+    fn tone_frequency(&self, kind: ChipChannelKind, frequency: f32) -> Option<f32> {
+        let timer: &ToneTimer = self.timer(kind)?;
+        let period: f32 = (self.clock / (timer.divider * frequency)).round();
+        if period < timer.min_period || period > timer.max_period {
+            return None;
+        }
+        Some(self.clock / (timer.divider * period))
+    }
+
+    fn noise_rate(&self, frequency: f32) -> f32 {
+        let target: f32 = frequency * NOISE_RATE_PER_HERTZ;
+        let mut best: f32 = self.clock / self.noise_dividers[0];
+
+        for shift in 0..self.noise_shifts {
+            for divider in self.noise_dividers {
+                let rate: f32 = self.clock / (divider * (1u32 << shift) as f32);
+                if (rate / target).log2().abs() < (best / target).log2().abs() {
+                    best = rate;
+                }
+            }
+        }
+        best
+    }
+
+    fn duty(&self, requested: f32) -> f32 {
+        self.duties
+            .iter()
+            .copied()
+            .min_by(|a, b| (a - requested).abs().total_cmp(&(b - requested).abs()))
+            .unwrap_or(DEFAULT_DUTY)
+    }
+
+    fn volume(&self, kind: ChipChannelKind, gain: f32) -> f32 {
+        let gain: f32 = gain.clamp(0.0, 1.0);
+
+        match kind {
+            // the NES triangle has no volume control, it is either running or silent
+            ChipChannelKind::Triangle => if gain > 0.0 { 1.0 } else { 0.0 },
+            ChipChannelKind::Wave => WAVE_CHANNEL_VOLUMES
+                .iter()
+                .copied()
+                .min_by(|a, b| (a - gain).abs().total_cmp(&(b - gain).abs()))
+                .unwrap_or(0.0),
+            _ if self.attenuation_volume => {
+                if gain <= 0.0 {
+                    return 0.0;
+                }
+                let steps: f32 = (-20.0 * gain.log10() / ATTENUATION_STEP_DB).round();
+                if steps >= ATTENUATION_STEPS {
+                    0.0
+                } else {
+                    10f32.powf(-steps * ATTENUATION_STEP_DB / 20.0)
+                }
+            }
+            _ => (gain * VOLUME_LEVELS).round() / VOLUME_LEVELS,
+        }
+    }
+}
+
+
+// what a note asks for, used to pick the channel type of its row
+#[derive(Clone, Copy, PartialEq)]
+enum NotePart {
+    Tone,
+    Triangle,
+    Noise,
+}
+
+impl NotePart {
+    fn of(waveform: Waveform) -> NotePart {
+        match waveform {
+            Waveform::Triangle => NotePart::Triangle,
+            Waveform::WhiteNoise | Waveform::PinkNoise => NotePart::Noise,
+            w if w.is_drum() => NotePart::Noise,
+            _ => NotePart::Tone,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            NotePart::Tone => "tone",
+            NotePart::Triangle => "triangle",
+            NotePart::Noise => "noise",
+        }
+    }
+
+    // channel types in the order they are tried
+    fn channels(self) -> &'static [ChipChannelKind] {
+        match self {
+            NotePart::Tone => &[ChipChannelKind::Pulse, ChipChannelKind::Wave],
+            NotePart::Triangle => &[ChipChannelKind::Triangle, ChipChannelKind::Wave, ChipChannelKind::Pulse],
+            NotePart::Noise => &[ChipChannelKind::Noise],
+        }
+    }
+
+    fn fits(self, kind: ChipChannelKind) -> bool {
+        (self == NotePart::Noise) == (kind == ChipChannelKind::Noise)
+    }
+}
+
+fn is_sounding(note: &NoteEvent) -> bool {
+    note.waveform != Waveform::Silence && note.gain > 0.0
+}
+
+fn requested_duty(note: &NoteEvent) -> f32 {
+    note.instrument.as_ref().filter(|i| i.has_pulse_duty()).map_or(DEFAULT_DUTY, |i| i.duty)
+}
+
+fn envelope(note: &NoteEvent) -> Option<&Envelope> {
+    note.instrument.as_ref().map(|i| &i.envelope).filter(|e| !e.is_flat())
+}
+
+fn cents_between(a: f32, b: f32) -> f32 {
+    1200.0 * (a / b).log2()
+}
+
+// everything about a note the chip cannot reproduce exactly
+fn note_warnings(spec: &ChipSpec, kind: ChipChannelKind, note: &NoteEvent) -> Vec<String> {
+    let mut warnings: Vec<String> = Vec::new();
+    let name: String = note.waveform.name();
+
+    if !NotePart::of(note.waveform).fits(kind) {
+        warnings.push(format!("{} cannot be played on the {} channel, the note is muted", name, kind.name()));
+        return warnings;
+    }
+
+    match kind {
+        ChipChannelKind::Pulse if note.waveform != Waveform::Square => {
+            warnings.push(format!("{} is voiced as a pulse wave", name));
+        }
+        ChipChannelKind::Triangle if note.waveform != Waveform::Triangle => {
+            warnings.push(format!("{} is voiced as a triangle wave", name));
+        }
+        ChipChannelKind::Wave if !matches!(note.waveform, Waveform::Triangle | Waveform::Sine | Waveform::Square | Waveform::Sawtooth) => {
+            warnings.push(format!("{} is voiced as a triangle wave", name));
+        }
+        ChipChannelKind::Noise if note.waveform != Waveform::WhiteNoise => {
+            warnings.push(format!("{} is voiced as the noise channel", name));
+        }
+        _ => {}
+    }
+
+    if kind == ChipChannelKind::Pulse {
+        let duty: f32 = requested_duty(note);
+        let played: f32 = spec.duty(duty);
+        if played != duty {
+            warnings.push(format!("duty {:.3} is played as {:.3}", duty, played));
+        }
+    }
+
+    if kind != ChipChannelKind::Noise {
+        match spec.tone_frequency(kind, note.frequency) {
+            Some(played) if cents_between(played, note.frequency).abs() > MAX_TUNING_CENTS => warnings.push(format!(
+                "the nearest playable pitch is {:+.0} cents away",
+                cents_between(played, note.frequency)
+            )),
+            Some(_) => {}
+            None => warnings.push(format!(
+                "the pitch is outside the range of the {} channel, the note is muted",
+                kind.name()
+            )),
+        }
+    }
+
+    if kind == ChipChannelKind::Triangle && (note.gain != 1.0 || envelope(note).is_some()) {
+        warnings.push(String::from("the triangle channel has no volume control, the note plays at full volume"));
+    } else if note.gain > 1.0 {
+        warnings.push(format!("gain {} is above full volume", note.gain));
+    }
+
+    if note.instrument.as_ref().is_some_and(|i| !i.effects.is_empty()) {
+        warnings.push(String::from("instrument effects cannot be reproduced and are left out"));
+    }
+
+    warnings
+}
+
+
+pub struct ChipPlan {
+    // one entry for every row of the song, in song order
+    pub voices: Vec<ChipVoice>,
+    pub warnings: Vec<String>,
+}
+
+// every row becomes one hardware channel for the whole song, taken in song order
+pub fn plan_song(all_notes: &[Vec<String>], config: &RenderConfig) -> Option<ChipPlan> {
+    let profile: ChipProfile = config.chip_profile;
+    let spec: &ChipSpec = chip_spec(profile)?;
+    let mut free: Vec<ChipChannelKind> = spec.channels.to_vec();
+    let mut voices: Vec<ChipVoice> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+
+    for (channel_index, rows) in all_notes.iter().enumerate() {
+        if config.channel_effects.get(channel_index).is_some_and(|c| !c.is_empty()) {
+            warnings.push(format!(
+                "channel {}: channel effects cannot be reproduced and are left out",
+                channel_index + 1
+            ));
+        }
+
+        for (row_index, row) in rows.iter().enumerate() {
+            let location: String = format!("channel {} row {}", channel_index + 1, row_index + 1);
+            let notes: Vec<NoteEvent> = parse_row(row, &config.sounds)?;
+
+            let Some(part) = notes.iter().find(|n| is_sounding(n)).map(|n| NotePart::of(n.waveform)) else {
+                voices.push(ChipVoice { profile, channel: None });
+                continue;
+            };
+
+            let channel: Option<ChipChannelKind> = part
+                .channels()
+                .iter()
+                .find_map(|kind| free.iter().position(|f| f == kind))
+                .map(|index| free.remove(index));
+
+            let Some(kind) = channel else {
+                warnings.push(format!(
+                    "{}: the {} has no channel left for {} notes, the row is muted",
+                    location, spec.name, part.name()
+                ));
+                voices.push(ChipVoice { profile, channel: None });
+                continue;
+            };
+
+            let tokens: Vec<&str> = row.split('>').filter(|t| !t.trim().is_empty()).collect();
+            for (note_index, note) in notes.iter().enumerate().filter(|(_, n)| is_sounding(n)) {
+                for warning in note_warnings(spec, kind, note) {
+                    warnings.push(format!(
+                        "{} note {} ({}): {}",
+                        location,
+                        note_index + 1,
+                        tokens.get(note_index).map_or("", |t| t.trim()),
+                        warning
+                    ));
+                }
+            }
+
+            voices.push(ChipVoice { profile, channel: Some(kind) });
+        }
+    }
+
+    Some(ChipPlan { voices, warnings })
+}
+
+
+// 15 bit linear feedback shift register shared by the three chips' long noise mode
+struct NoiseRegister {
+    register: u16,
+    clock_phase: f32,
+}
+
+impl NoiseRegister {
+    fn new() -> NoiseRegister {
+        NoiseRegister { register: 1, clock_phase: 0.0 }
+    }
+
+    fn next_sample(&mut self, rate: f32, sample_rate: f32) -> f32 {
+        self.clock_phase += rate / sample_rate;
+        while self.clock_phase >= 1.0 {
+            let feedback: u16 = (self.register ^ (self.register >> 1)) & 1;
+            self.register = (self.register >> 1) | (feedback << 14);
+            self.clock_phase -= 1.0;
+        }
+        if self.register & 1 == 0 { 1.0 } else { -1.0 }
+    }
+}
+
+fn wave_table_sample(waveform: Waveform, duty: f32, phase: f32) -> f32 {
+    let step: f32 = (phase.rem_euclid(1.0) * WAVE_TABLE_STEPS).floor();
+
+    let value: f32 = match waveform {
+        // the NES sequence, counting down from 15 and back up
+        Waveform::Triangle => {
+            let level: f32 = if step < 16.0 { 15.0 - step } else { step - 16.0 };
+            return level / (WAVE_TABLE_LEVELS / 2.0) - 1.0;
+        }
+        Waveform::Square => pulse_sample(step / WAVE_TABLE_STEPS, duty),
+        Waveform::Sine | Waveform::Sawtooth => oscillator_sample(waveform, step / WAVE_TABLE_STEPS),
+        _ => return wave_table_sample(Waveform::Triangle, duty, phase),
+    };
+
+    ((value + 1.0) * WAVE_TABLE_LEVELS / 2.0).round() / (WAVE_TABLE_LEVELS / 2.0) - 1.0
+}
+
+// the oscillator and the noise register keep running from note to note, as on the hardware
+pub fn generate_chip_row(input: &str, voice: ChipVoice, config: &RenderConfig, progress: &Progress) -> Option<Vec<f32>> {
+    let sample_rate: f32 = config.sample_rate_f32();
+    let spec: Option<&ChipSpec> = chip_spec(voice.profile);
+    let mut row_wave: Vec<f32> = Vec::new();
+    let mut phase: f32 = 0.0;
+    let mut noise: NoiseRegister = NoiseRegister::new();
+
+    for note in parse_row(input, &config.sounds)? {
+        let length: usize = milliseconds_to_samples(note.milliseconds, sample_rate);
+
+        let playable: Option<(&ChipSpec, ChipChannelKind, f32)> = match (spec, voice.channel) {
+            (Some(spec), Some(kind)) if is_sounding(&note) && NotePart::of(note.waveform).fits(kind) => {
+                let frequency: Option<f32> = match kind {
+                    ChipChannelKind::Noise => Some(spec.noise_rate(note.frequency)),
+                    _ => spec.tone_frequency(kind, note.frequency),
+                };
+                frequency.map(|f| (spec, kind, f))
+            }
+            _ => None,
+        };
+
+        let Some((spec, kind, frequency)) = playable else {
+            row_wave.extend(std::iter::repeat_n(0.0, length));
+            if !progress.advance(length as u64) {
+                return None;
+            }
+            continue;
+        };
+
+        let duty: f32 = spec.duty(requested_duty(&note));
+        let envelope: Option<&Envelope> = envelope(&note);
+
+        for position in 0..length {
+            let gain: f32 = note.gain * envelope.map_or(1.0, |e| e.gain_at(position, length, sample_rate));
+
+            let value: f32 = match kind {
+                ChipChannelKind::Pulse => pulse_sample(phase, duty),
+                ChipChannelKind::Triangle => wave_table_sample(Waveform::Triangle, duty, phase),
+                ChipChannelKind::Wave => wave_table_sample(note.waveform, duty, phase),
+                ChipChannelKind::Noise => noise.next_sample(frequency, sample_rate),
+            };
+            phase = (phase + frequency / sample_rate).fract();
+
+            row_wave.push(value * spec.volume(kind, gain));
+        }
+
+        if !progress.advance(length as u64) {
+            return None;
+        }
+    }
+
+    Some(row_wave)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(profile: ChipProfile) -> RenderConfig {
+        let mut config: RenderConfig = RenderConfig::from_globals().unwrap();
+        config.sample_rate = 44100;
+        config.chip_profile = profile;
+        config.channel_effects = Vec::new();
+        config
+    }
+
+    fn song(rows: &[&str]) -> Vec<Vec<String>> {
+        vec![rows.iter().map(|r| r.to_string()).collect()]
+    }
+
+    #[test]
+    fn pitches_snap_to_the_period_registers() {
+        let nes_pulse: f32 = NES.tone_frequency(ChipChannelKind::Pulse, 440.0).unwrap();
+        assert!((nes_pulse - 1_789_773.0 / (16.0 * 254.0)).abs() < 1e-3);
+        let nes_triangle: f32 = NES.tone_frequency(ChipChannelKind::Triangle, 440.0).unwrap();
+        assert!((nes_triangle - 1_789_773.0 / (32.0 * 127.0)).abs() < 1e-3);
+        let game_boy: f32 = GAME_BOY.tone_frequency(ChipChannelKind::Pulse, 440.0).unwrap();
+        assert!((game_boy - 4_194_304.0 / (32.0 * 298.0)).abs() < 1e-3);
+
+        assert!(NES.tone_frequency(ChipChannelKind::Pulse, 16.35).is_none());
+        assert!(SN76489.tone_frequency(ChipChannelKind::Pulse, 27.5).is_none());
+        assert!(NES.tone_frequency(ChipChannelKind::Wave, 440.0).is_none());
+    }
+
+    #[test]
+    fn volumes_and_duties_snap_to_the_hardware_steps() {
+        assert_eq!(NES.volume(ChipChannelKind::Pulse, 0.5), 8.0 / 15.0);
+        assert_eq!(NES.volume(ChipChannelKind::Pulse, 2.0), 1.0);
+        assert_eq!(NES.volume(ChipChannelKind::Triangle, 0.2), 1.0);
+        assert_eq!(GAME_BOY.volume(ChipChannelKind::Wave, 0.3), 0.25);
+        assert!((SN76489.volume(ChipChannelKind::Pulse, 0.5) - 10f32.powf(-6.0 / 20.0)).abs() < 1e-6);
+        assert_eq!(SN76489.volume(ChipChannelKind::Pulse, 0.001), 0.0);
+
+        assert_eq!(NES.duty(0.3), 0.25);
+        assert_eq!(GAME_BOY.duty(0.7), 0.75);
+        assert_eq!(SN76489.duty(0.125), 0.5);
+    }
+
+    #[test]
+    fn wave_tables_hold_16_levels() {
+        let levels: Vec<f32> = (0..32).map(|step| wave_table_sample(Waveform::Triangle, 0.5, step as f32 / 32.0)).collect();
+        assert_eq!(levels[0], 1.0);
+        assert_eq!(levels[15], -1.0);
+        assert_eq!(levels[31], 1.0);
+
+        for waveform in [Waveform::Triangle, Waveform::Sine, Waveform::Sawtooth, Waveform::Square] {
+            for n in 0..256 {
+                let level: f32 = (wave_table_sample(waveform, 0.5, n as f32 / 256.0) + 1.0) * 7.5;
+                assert!((level - level.round()).abs() < 1e-4 && (0.0..=15.0).contains(&level), "{}", level);
+            }
+        }
+    }
+
+    #[test]
+    fn rows_take_channels_in_song_order() {
+        let plan: ChipPlan = plan_song(
+            &song(&["A4_100_1_Square", "A4_100_1_Triangle", "C0_100_0_Silence", "A4_100_1_WhiteNoise", "A4_100_1_Square", "A4_100_1_Sine"]),
+            &config(ChipProfile::Nes),
+        )
+        .unwrap();
+
+        let channels: Vec<Option<&str>> = plan.voices.iter().map(|v| v.channel.map(|k| k.name())).collect();
+        assert_eq!(channels, [Some("pulse"), Some("triangle"), None, Some("noise"), Some("pulse"), None]);
+        assert_eq!(plan.warnings, ["channel 1 row 6: the NES has no channel left for tone notes, the row is muted"]);
+    }
+
+    #[test]
+    fn unplayable_notes_are_reported() {
+        let plan: ChipPlan = plan_song(
+            &song(&["C0_100_1_Square>A4_100_2_Sine", "A4+40_100_0.5_Triangle"]),
+            &config(ChipProfile::Nes),
+        )
+        .unwrap();
+
+        assert_eq!(plan.warnings, [
+            "channel 1 row 1 note 1 (C0_100_1_Square): the pitch is outside the range of the pulse channel, the note is muted",
+            "channel 1 row 1 note 2 (A4_100_2_Sine): Sine is voiced as a pulse wave",
+            "channel 1 row 1 note 2 (A4_100_2_Sine): gain 2 is above full volume",
+            "channel 1 row 2 note 1 (A4+40_100_0.5_Triangle): the triangle channel has no volume control, the note plays at full volume",
+        ]);
+
+        let plan: ChipPlan = plan_song(&song(&["A4_100_1_Sine"]), &config(ChipProfile::GameBoy)).unwrap();
+        assert_eq!(plan.warnings, ["channel 1 row 1 note 1 (A4_100_1_Sine): Sine is voiced as a pulse wave"]);
+    }
+
+    #[test]
+    fn chip_rows_use_the_quantised_levels() {
+        let config: RenderConfig = config(ChipProfile::Nes);
+        let pulse: ChipVoice = ChipVoice { profile: ChipProfile::Nes, channel: Some(ChipChannelKind::Pulse) };
+        let noise: ChipVoice = ChipVoice { profile: ChipProfile::Nes, channel: Some(ChipChannelKind::Noise) };
+
+        let row: Vec<f32> = generate_chip_row("A4_100_0.5_Square>C0_100_1_Square", pulse, &config, &Progress::new()).unwrap();
+        assert_eq!(row.len(), 8820);
+        assert!(row[..4410].iter().all(|s| s.abs() == 8.0 / 15.0));
+        assert!(row[4410..].iter().all(|&s| s == 0.0));
+
+        let hit: Vec<f32> = generate_chip_row("A4_100_1_WhiteNoise", noise, &config, &Progress::new()).unwrap();
+        assert_eq!(hit, generate_chip_row("A4_100_1_WhiteNoise", noise, &config, &Progress::new()).unwrap());
+        assert!(hit.iter().all(|s| s.abs() == 1.0));
+    }
+}
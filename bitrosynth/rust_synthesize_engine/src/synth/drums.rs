@@ -0,0 +1,250 @@
+use std::f32::consts;
+
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+use serde::{Deserialize, Serialize};
+
+use crate::common_types::Waveform;
+use crate::utils::milliseconds_to_samples;
+
+
+// every hit starts from the same noise, so drum rows render identically and can be cached
+const DRUM_NOISE_SEED: u64 = 0x4254_5359;
+
+// square partials of the classic analogue hi-hat, relative to the note frequency
+const HI_HAT_RATIOS: [f32; 6] = [1.0, 1.4826, 1.8002, 2.5459, 2.6303, 3.8965];
+const HI_HAT_BASE_FREQUENCY: f32 = 205.3;
+
+const CLAP_BURST_SECONDS: f32 = 0.01;
+const CLAP_BURSTS: f32 = 3.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DrumSettings {
+    // scales the natural decay time of the drum
+    pub decay: f32,
+    // noise against tone balance, 0 is all tone and 1 all noise
+    pub tone: f32,
+    // depth of the falling pitch of kicks and toms
+    pub sweep: f32,
+}
+
+impl Default for DrumSettings {
+    fn default() -> DrumSettings {
+        DrumSettings { decay: 1.0, tone: 0.5, sweep: 1.0 }
+    }
+}
+
+
+struct OnePoleHighPass {
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl OnePoleHighPass {
+    fn new() -> OnePoleHighPass {
+        OnePoleHighPass { previous_input: 0.0, previous_output: 0.0 }
+    }
+
+    fn process(&mut self, input: f32, cutoff: f32, sample_rate: f32) -> f32 {
+        let rc: f32 = 1.0 / (2.0 * consts::PI * cutoff);
+        let alpha: f32 = rc / (rc + 1.0 / sample_rate);
+        self.previous_output = alpha * (self.previous_output + input - self.previous_input);
+        self.previous_input = input;
+        self.previous_output
+    }
+}
+
+struct OnePoleLowPass {
+    previous_output: f32,
+}
+
+impl OnePoleLowPass {
+    fn new() -> OnePoleLowPass {
+        OnePoleLowPass { previous_output: 0.0 }
+    }
+
+    fn process(&mut self, input: f32, cutoff: f32, sample_rate: f32) -> f32 {
+        let dt: f32 = 1.0 / sample_rate;
+        let alpha: f32 = dt / (dt + 1.0 / (2.0 * consts::PI * cutoff));
+        self.previous_output += alpha * (input - self.previous_output);
+        self.previous_output
+    }
+}
+
+
+pub struct DrumVoice {
+    waveform: Waveform,
+    frequency: f32,
+    settings: DrumSettings,
+    position: u64,
+    phase: f32,
+    partial_phases: [f32; 6],
+    rng: SmallRng,
+    high_pass: OnePoleHighPass,
+    second_high_pass: OnePoleHighPass,
+    low_pass: OnePoleLowPass,
+}
+
+fn decay_envelope(seconds: f32, time_constant: f32) -> f32 {
+    (-seconds / time_constant.max(1e-4)).exp()
+}
+
+impl DrumVoice {
+    pub fn new(waveform: Waveform, frequency: f32, settings: DrumSettings) -> DrumVoice {
+        DrumVoice {
+            waveform,
+            frequency,
+            settings,
+            position: 0,
+            phase: 0.0,
+            partial_phases: [0.0; 6],
+            rng: SmallRng::seed_from_u64(DRUM_NOISE_SEED),
+            high_pass: OnePoleHighPass::new(),
+            second_high_pass: OnePoleHighPass::new(),
+            low_pass: OnePoleLowPass::new(),
+        }
+    }
+
+    fn noise(&mut self) -> f32 {
+        self.rng.random_range(-1.0..=1.0)
+    }
+
+    // sine whose pitch falls from a multiple of the note frequency towards the note itself
+    fn swept_sine(&mut self, seconds: f32, start_ratio: f32, sweep_time: f32, sample_rate: f32) -> f32 {
+        let start: f32 = self.frequency * (1.0 + (start_ratio - 1.0) * self.settings.sweep.max(0.0));
+        let frequency: f32 = self.frequency + (start - self.frequency) * decay_envelope(seconds, sweep_time);
+        let value: f32 = (2.0 * consts::PI * self.phase).sin();
+        self.phase = (self.phase + frequency / sample_rate).fract();
+        value
+    }
+
+    fn kick(&mut self, seconds: f32, sample_rate: f32) -> f32 {
+        let body: f32 = self.swept_sine(seconds, 6.0, 0.03, sample_rate) * decay_envelope(seconds, 0.25 * self.settings.decay);
+        let click: f32 = self.noise() * decay_envelope(seconds, 0.002) * self.settings.tone;
+        body + click
+    }
+
+    fn tom(&mut self, seconds: f32, sample_rate: f32) -> f32 {
+        self.swept_sine(seconds, 2.0, 0.05, sample_rate) * decay_envelope(seconds, 0.35 * self.settings.decay)
+    }
+
+    fn snare(&mut self, seconds: f32, sample_rate: f32) -> f32 {
+        let body: f32 = self.swept_sine(seconds, 1.5, 0.01, sample_rate) * decay_envelope(seconds, 0.08 * self.settings.decay);
+        let white: f32 = self.noise();
+        let noise: f32 = self.high_pass.process(white, 1000.0, sample_rate) * decay_envelope(seconds, 0.15 * self.settings.decay);
+        body * (1.0 - self.settings.tone) + noise * self.settings.tone * 1.5
+    }
+
+    fn hi_hat(&mut self, seconds: f32, decay_time: f32, sample_rate: f32) -> f32 {
+        // the cluster keeps the analogue spacing and moves with the note relative to A4
+        let base: f32 = HI_HAT_BASE_FREQUENCY * self.frequency / 440.0;
+        let mut metallic: f32 = 0.0;
+        for (phase, ratio) in self.partial_phases.iter_mut().zip(HI_HAT_RATIOS) {
+            metallic += if *phase < 0.5 { 1.0 } else { -1.0 };
+            *phase = (*phase + base * ratio / sample_rate).fract();
+        }
+        metallic /= HI_HAT_RATIOS.len() as f32;
+
+        let source: f32 = metallic * (1.0 - self.settings.tone) + self.noise() * self.settings.tone;
+        let cutoff: f32 = (7000.0f32).min(sample_rate * 0.45);
+        let filtered: f32 = self.high_pass.process(source, cutoff, sample_rate);
+        let filtered: f32 = self.second_high_pass.process(filtered, cutoff, sample_rate);
+
+        (filtered * 2.0) * decay_envelope(seconds, decay_time * self.settings.decay)
+    }
+
+    fn clap(&mut self, seconds: f32, sample_rate: f32) -> f32 {
+        let white: f32 = self.noise();
+        let band: f32 = self.low_pass.process(self.high_pass.process(white, 800.0, sample_rate), 2500.0, sample_rate);
+
+        // a few quick bursts of hands followed by the room tail
+        let burst_index: f32 = (seconds / CLAP_BURST_SECONDS).floor();
+        let envelope: f32 = if burst_index < CLAP_BURSTS {
+            decay_envelope(seconds - burst_index * CLAP_BURST_SECONDS, 0.004)
+        } else {
+            decay_envelope(seconds - CLAP_BURSTS * CLAP_BURST_SECONDS, 0.12 * self.settings.decay)
+        };
+
+        band * envelope * 3.0
+    }
+
+    pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
+        let seconds: f32 = self.position as f32 / sample_rate;
+        self.position += 1;
+
+        let value: f32 = match self.waveform {
+            Waveform::Kick => self.kick(seconds, sample_rate),
+            Waveform::Snare => self.snare(seconds, sample_rate),
+            Waveform::ClosedHiHat => self.hi_hat(seconds, 0.04, sample_rate),
+            Waveform::OpenHiHat => self.hi_hat(seconds, 0.3, sample_rate),
+            Waveform::Tom => self.tom(seconds, sample_rate),
+            Waveform::Clap => self.clap(seconds, sample_rate),
+            _ => 0.0,
+        };
+
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+
+pub fn generate_drum(
+    waveform: Waveform,
+    milliseconds: u32,
+    frequency: f32,
+    settings: DrumSettings,
+    sample_rate: f32
+) -> Vec<f32> {
+    let mut voice: DrumVoice = DrumVoice::new(waveform, frequency, settings);
+    (0..milliseconds_to_samples(milliseconds, sample_rate))
+        .map(|_| voice.next_sample(sample_rate))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRUMS: [Waveform; 6] =
+        [Waveform::Kick, Waveform::Snare, Waveform::ClosedHiHat, Waveform::OpenHiHat, Waveform::Tom, Waveform::Clap];
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn hits_are_deterministic_and_bounded() {
+        for drum in DRUMS {
+            let hit: Vec<f32> = generate_drum(drum, 500, 440.0, DrumSettings::default(), 44100.0);
+            assert_eq!(hit.len(), 22050);
+            assert_eq!(hit, generate_drum(drum, 500, 440.0, DrumSettings::default(), 44100.0));
+            assert!(hit.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+            assert!(rms(&hit[..2205]) > 0.01);
+        }
+    }
+
+    #[test]
+    fn hits_fade_and_decay_stretches_them() {
+        let long: DrumSettings = DrumSettings { decay: 3.0, ..DrumSettings::default() };
+
+        for drum in DRUMS {
+            let hit: Vec<f32> = generate_drum(drum, 500, 440.0, DrumSettings::default(), 44100.0);
+            let long_hit: Vec<f32> = generate_drum(drum, 500, 440.0, long, 44100.0);
+            assert!(rms(&hit[19845..]) < rms(&hit[..4410]) * 0.4);
+            assert!(rms(&long_hit[19845..]) > rms(&hit[19845..]));
+        }
+    }
+
+    #[test]
+    fn kick_pitch_falls_towards_the_note() {
+        let no_click: DrumSettings = DrumSettings { tone: 0.0, ..DrumSettings::default() };
+        let kick: Vec<f32> = generate_drum(Waveform::Kick, 400, 55.0, no_click, 44100.0);
+        let crossings = |samples: &[f32]| samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count();
+
+        // 55 Hz alone would cross about 3 times in the first 50 ms
+        assert!(crossings(&kick[..2205]) >= 6);
+        // the swept sine settles on 55 Hz, about 11 cycles in the last 200 ms
+        assert!((10..=12).contains(&crossings(&kick[8820..])));
+    }
+}
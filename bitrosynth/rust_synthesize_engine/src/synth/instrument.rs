@@ -0,0 +1,169 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common_types::Waveform;
+use crate::effects::EffectSettings;
+use crate::global_state::{INSTRUMENTS, SOUND_GENERATION};
+use crate::render_cache::clear_row_cache;
+use crate::synth::drums::DrumSettings;
+use crate::synth::oscillators::FmSettings;
+use crate::utils::milliseconds_to_samples;
+
+
+pub const DEFAULT_DUTY: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub attack_milliseconds: u32,
+    pub decay_milliseconds: u32,
+    pub sustain: f32,
+    pub release_milliseconds: u32,
+}
+
+impl Envelope {
+    pub fn is_flat(&self) -> bool {
+        *self == Envelope::default()
+    }
+
+    // the release is taken from the end of the note, so notes keep their written length
+    pub fn gain_at(&self, position: usize, length: usize, sample_rate: f32) -> f32 {
+        let attack: usize = milliseconds_to_samples(self.attack_milliseconds, sample_rate);
+        let decay: usize = milliseconds_to_samples(self.decay_milliseconds, sample_rate);
+        let release: usize = milliseconds_to_samples(self.release_milliseconds, sample_rate).min(length);
+
+        let level: f32 = if position < attack {
+            position as f32 / attack as f32
+        } else if position < attack + decay {
+            1.0 - (1.0 - self.sustain) * (position - attack) as f32 / decay as f32
+        } else {
+            self.sustain
+        };
+
+        let release_start: usize = length - release;
+        if position >= release_start && release > 0 {
+            level * (length - position) as f32 / release as f32
+        } else {
+            level
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope {
+            attack_milliseconds: 0,
+            decay_milliseconds: 0,
+            sustain: 1.0,
+            release_milliseconds: 0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Instrument {
+    pub name: String,
+    pub waveform: Waveform,
+    pub gain: f32,
+    #[serde(default)]
+    pub envelope: Envelope,
+    #[serde(default)]
+    pub detune_cents: f32,
+    // share of the cycle a square wave spends high
+    #[serde(default = "default_duty")]
+    pub duty: f32,
+    // only read when the waveform is one of the drums
+    #[serde(default)]
+    pub drum: DrumSettings,
+    // only read when the waveform is Fm2 or Fm4
+    #[serde(default)]
+    pub fm: FmSettings,
+    #[serde(default)]
+    pub effects: Vec<EffectSettings>,
+}
+
+fn default_duty() -> f32 {
+    DEFAULT_DUTY
+}
+
+impl Instrument {
+    // the same JSON object a project stores in "instruments"
+    pub fn from_json(text: &str) -> Option<Instrument> {
+        serde_json::from_str(text).ok()
+    }
+
+    // names take the place of the waveform field, so they must not look like one or split a token
+    pub fn is_valid(&self) -> bool {
+        !self.name.is_empty()
+            && !self.name.contains(['_', '>', ' '])
+            && Waveform::from_name(&self.name).is_none()
+            && self.gain.is_finite()
+            && self.gain >= 0.0
+            && self.detune_cents.is_finite()
+            && self.duty > 0.0
+            && self.duty < 1.0
+            && (0.0..=1.0).contains(&self.envelope.sustain)
+            && self.drum.decay > 0.0
+            && (0.0..=1.0).contains(&self.drum.tone)
+            && self.drum.sweep >= 0.0
+            && self.fm.is_valid()
+    }
+
+    pub fn has_pulse_duty(&self) -> bool {
+        self.waveform == Waveform::Square && self.duty != DEFAULT_DUTY
+    }
+}
+
+
+// rows naming a replaced instrument must be rendered again, so the row cache is dropped
+pub fn register_instrument(instrument: Instrument) -> bool {
+    if !instrument.is_valid() {
+        return false;
+    }
+
+    match INSTRUMENTS.lock() {
+        Ok(mut instruments) => {
+            instruments.insert(instrument.name.clone(), Arc::new(instrument));
+            SOUND_GENERATION.fetch_add(1, Ordering::SeqCst);
+        }
+        Err(_) => return false,
+    }
+    clear_row_cache();
+    true
+}
+
+pub fn remove_instrument(name: &str) -> bool {
+    let removed: bool = match INSTRUMENTS.lock() {
+        Ok(mut instruments) => {
+            SOUND_GENERATION.fetch_add(1, Ordering::SeqCst);
+            instruments.remove(name).is_some()
+        }
+        Err(_) => false,
+    };
+    clear_row_cache();
+    removed
+}
+
+pub fn set_instruments(list: &[Instrument]) -> bool {
+    if !list.iter().all(|i| i.is_valid()) {
+        return false;
+    }
+
+    match INSTRUMENTS.lock() {
+        Ok(mut instruments) => {
+            *instruments = list.iter().map(|i| (i.name.clone(), Arc::new(i.clone()))).collect();
+            SOUND_GENERATION.fetch_add(1, Ordering::SeqCst);
+        }
+        Err(_) => return false,
+    }
+    clear_row_cache();
+    true
+}
+
+pub fn registered_instruments() -> Vec<Instrument> {
+    match INSTRUMENTS.lock() {
+        Ok(instruments) => instruments.values().map(|i| Instrument::clone(i)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
@@ -1,3 +1,9 @@
 pub mod oscillators;
 pub mod noise;
 pub mod channel;
+pub mod voice;
+pub mod sampler;
+pub mod instrument;
+pub mod drums;
+pub mod chip;
+pub mod sound_bank;
@@ -1,67 +1,298 @@
-use crate::global_state::SAMPLE_RATE;
-use std::{f32::consts, sync::atomic::Ordering};
+use crate::common_types::Waveform;
+use crate::utils::milliseconds_to_samples;
+use std::f32::consts;
 
+use serde::{Deserialize, Serialize};
 
-pub fn generate_triangle(milliseconds: u32, frequency: f32) -> Vec<f32> {
-    let sample_rate: f32 = SAMPLE_RATE.load(Ordering::SeqCst) as f32;
-    let seconds: f32 = milliseconds as f32 / 1000.0;
-    let total_samples: usize = (seconds * sample_rate) as usize;
-    let mut output: Vec<f32> = Vec::with_capacity(total_samples);
+use super::instrument::Envelope;
 
-    for n in 0..total_samples {
-        let t: f32 = n as f32 / sample_rate;
-        let value: f32 = 4.0 * ((frequency * t - 0.25).fract() - 0.5).abs() - 1.0;
-        output.push(value);
+
+// phase is measured in cycles, so the same shapes serve offline rendering and live voices
+pub fn oscillator_sample(waveform: Waveform, phase: f32) -> f32 {
+    match waveform {
+        // rem_euclid keeps the wrapped phase positive, fract left the first quarter cycle between 1 and 2
+        Waveform::Triangle => 4.0 * ((phase - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0,
+        Waveform::Square => {
+            if (2.0 * consts::PI * phase).sin() >= 0.0 {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+        Waveform::Sine => (2.0 * consts::PI * phase).sin(),
+        Waveform::Sawtooth => 2.0 * (phase - phase.floor()) - 1.0,
+        _ => 0.0,
     }
+}
 
-    return output;
+// square wave with an adjustable high share, used by instruments with a duty other than 0.5
+pub fn pulse_sample(phase: f32, duty: f32) -> f32 {
+    if phase.rem_euclid(1.0) < duty { 1.0 } else { -1.0 }
 }
 
-pub fn generate_square(milliseconds: u32, frequency: f32) -> Vec<f32> {
-    let sample_rate: f32 = SAMPLE_RATE.load(Ordering::SeqCst) as f32;
+fn generate_periodic(waveform: Waveform, milliseconds: u32, frequency: f32, sample_rate: f32) -> Vec<f32> {
     let seconds: f32 = milliseconds as f32 / 1000.0;
     let total_samples: usize = (seconds * sample_rate) as usize;
     let mut output: Vec<f32> = Vec::with_capacity(total_samples);
 
     for n in 0..total_samples {
         let t: f32 = n as f32 / sample_rate;
-        let value: f32 = if (2.0 * std::f32::consts::PI * frequency * t).sin() >= 0.0 {
-            1.0
-        } else {
-            -1.0
-        };
-        output.push(value);
+        output.push(oscillator_sample(waveform, frequency * t));
     }
 
-    return output;
+    output
+}
+
+pub fn generate_triangle(milliseconds: u32, frequency: f32, sample_rate: f32) -> Vec<f32> {
+    generate_periodic(Waveform::Triangle, milliseconds, frequency, sample_rate)
+}
+
+pub fn generate_square(milliseconds: u32, frequency: f32, sample_rate: f32) -> Vec<f32> {
+    generate_periodic(Waveform::Square, milliseconds, frequency, sample_rate)
+}
+
+pub fn generate_sine(milliseconds: u32, frequency: f32, sample_rate: f32) -> Vec<f32> {
+    generate_periodic(Waveform::Sine, milliseconds, frequency, sample_rate)
 }
 
-pub fn generate_sine(milliseconds: u32, frequency: f32) -> Vec<f32> {
-    let sample_rate: f32 = SAMPLE_RATE.load(Ordering::SeqCst) as f32;
+pub fn generate_sawtooth(milliseconds: u32, frequency: f32, sample_rate: f32) -> Vec<f32> {
+    generate_periodic(Waveform::Sawtooth, milliseconds, frequency, sample_rate)
+}
+
+pub fn generate_pulse(milliseconds: u32, frequency: f32, duty: f32, sample_rate: f32) -> Vec<f32> {
     let seconds: f32 = milliseconds as f32 / 1000.0;
     let total_samples: usize = (seconds * sample_rate) as usize;
-    let mut output: Vec<f32> = Vec::with_capacity(total_samples);
 
-    for n in 0..total_samples {
-        let t = n as f32 / sample_rate;
-        let value = (2.0 * consts::PI * frequency * t).sin();
-        output.push(value);
+    (0..total_samples)
+        .map(|n| pulse_sample(frequency * (n as f32 / sample_rate), duty))
+        .collect()
+}
+
+
+// FM voices, operators are numbered from the carrier side and the highest one has feedback
+const MAX_FEEDBACK: f32 = consts::PI;
+
+// (modulator, target) pairs, a modulator always has a higher index than its target,
+// followed by the operators that are heard
+type FmAlgorithm = (&'static [(usize, usize)], &'static [usize]);
+
+const TWO_OPERATOR_ALGORITHMS: [FmAlgorithm; 2] = [
+    (&[(1, 0)], &[0]),
+    (&[], &[0, 1]),
+];
+
+const FOUR_OPERATOR_ALGORITHMS: [FmAlgorithm; 5] = [
+    (&[(3, 2), (2, 1), (1, 0)], &[0]),
+    (&[(3, 2), (1, 0)], &[0, 2]),
+    (&[(3, 1), (2, 1), (1, 0)], &[0]),
+    (&[(3, 0), (3, 1), (3, 2)], &[0, 1, 2]),
+    (&[], &[0, 1, 2, 3]),
+];
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FmOperator {
+    // frequency relative to the note
+    pub ratio: f32,
+    // output level of a carrier, modulation index in radians of a modulator
+    pub level: f32,
+    #[serde(default)]
+    pub envelope: Envelope,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FmSettings {
+    pub algorithm: usize,
+    // self modulation of the highest operator, 0 to 1
+    pub feedback: f32,
+    // Fm2 only uses the first two operators
+    pub operators: [FmOperator; 4],
+}
+
+impl FmSettings {
+    pub fn is_valid(&self) -> bool {
+        self.algorithm < FOUR_OPERATOR_ALGORITHMS.len()
+            && (0.0..=1.0).contains(&self.feedback)
+            && self.operators.iter().all(|o| {
+                o.ratio.is_finite() && o.ratio > 0.0 && o.level.is_finite() && o.level >= 0.0
+                    && (0.0..=1.0).contains(&o.envelope.sustain)
+            })
     }
+}
 
-    return output;
+impl Default for FmSettings {
+    // a bright electric piano like tone whose modulation fades after the attack
+    fn default() -> FmSettings {
+        let fading: Envelope = Envelope { attack_milliseconds: 0, decay_milliseconds: 300, sustain: 0.3, release_milliseconds: 0 };
+        FmSettings {
+            algorithm: 0,
+            feedback: 0.0,
+            operators: [
+                FmOperator { ratio: 1.0, level: 1.0, envelope: Envelope::default() },
+                FmOperator { ratio: 2.0, level: 2.0, envelope: fading },
+                FmOperator { ratio: 3.0, level: 1.0, envelope: fading },
+                FmOperator { ratio: 1.0, level: 0.5, envelope: Envelope::default() },
+            ],
+        }
+    }
 }
 
-pub fn generate_sawtooth(milliseconds: u32, frequency: f32) -> Vec<f32> {
-    let sample_rate: f32 = SAMPLE_RATE.load(Ordering::SeqCst) as f32;
-    let seconds: f32 = milliseconds as f32 / 1000.0;
-    let total_samples: usize = (seconds * sample_rate) as usize;
-    let mut output: Vec<f32> = Vec::with_capacity(total_samples);
+pub struct FmVoice {
+    settings: FmSettings,
+    algorithm: FmAlgorithm,
+    operator_count: usize,
+    frequency: f32,
+    milliseconds: Option<u32>,
+    position: usize,
+    phases: [f32; 4],
+    feedback_history: [f32; 2],
+}
 
-    for n in 0..total_samples {
-        let t: f32 = n as f32 / sample_rate;
-        let value: f32 = 2.0 * (frequency * t - (frequency * t).floor()) - 1.0;
-        output.push(value);
+impl FmVoice {
+    // without a length the envelopes never release, as for held live notes
+    pub fn new(waveform: Waveform, settings: FmSettings, frequency: f32, milliseconds: Option<u32>) -> FmVoice {
+        let (operator_count, algorithm): (usize, FmAlgorithm) = match waveform {
+            Waveform::Fm2 => (2, TWO_OPERATOR_ALGORITHMS[settings.algorithm.min(1)]),
+            _ => (4, FOUR_OPERATOR_ALGORITHMS[settings.algorithm.min(FOUR_OPERATOR_ALGORITHMS.len() - 1)]),
+        };
+
+        FmVoice {
+            settings,
+            algorithm,
+            operator_count,
+            frequency,
+            milliseconds,
+            position: 0,
+            phases: [0.0; 4],
+            feedback_history: [0.0; 2],
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
+        let length: usize = self.milliseconds.map_or(usize::MAX, |ms| milliseconds_to_samples(ms, sample_rate));
+        let (connections, carriers) = self.algorithm;
+        let mut outputs: [f32; 4] = [0.0; 4];
+
+        for index in (0..self.operator_count).rev() {
+            let operator: FmOperator = self.settings.operators[index];
+
+            let mut modulation: f32 = connections
+                .iter()
+                .filter(|(_, target)| *target == index)
+                .map(|(source, _)| outputs[*source])
+                .sum();
+            if index == self.operator_count - 1 {
+                modulation += (self.feedback_history[0] + self.feedback_history[1]) * 0.5 * self.settings.feedback * MAX_FEEDBACK;
+            }
+
+            let envelope: f32 = if operator.envelope.is_flat() {
+                1.0
+            } else {
+                operator.envelope.gain_at(self.position, length, sample_rate)
+            };
+            outputs[index] = (2.0 * consts::PI * self.phases[index] + modulation).sin() * operator.level * envelope;
+
+            self.phases[index] = (self.phases[index] + self.frequency * operator.ratio / sample_rate).fract();
+        }
+
+        let top: usize = self.operator_count - 1;
+        self.feedback_history = [outputs[top], self.feedback_history[0]];
+        self.position += 1;
+
+        carriers.iter().map(|c| outputs[*c]).sum::<f32>() / carriers.len() as f32
+    }
+}
+
+pub fn generate_fm(
+    waveform: Waveform,
+    settings: FmSettings,
+    milliseconds: u32,
+    frequency: f32,
+    sample_rate: f32
+) -> Vec<f32> {
+    let mut voice: FmVoice = FmVoice::new(waveform, settings, frequency, Some(milliseconds));
+    (0..milliseconds_to_samples(milliseconds, sample_rate))
+        .map(|_| voice.next_sample(sample_rate))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangle_rises_from_zero_without_overshooting() {
+        for step in 0..=100 {
+            let phase: f32 = step as f32 / 400.0;
+            let value: f32 = oscillator_sample(Waveform::Triangle, phase);
+            assert!((value - 4.0 * phase).abs() < 1e-5, "{} at phase {}", value, phase);
+        }
+
+        let cycle: Vec<f32> = (0..400).map(|n| oscillator_sample(Waveform::Triangle, n as f32 / 400.0)).collect();
+        assert!(cycle.iter().all(|v| v.abs() <= 1.0));
+        assert_eq!(cycle[0], 0.0);
+        assert_eq!(cycle[300], -1.0);
+    }
+
+    fn fm_settings(algorithm: usize, feedback: f32, levels: [f32; 4]) -> FmSettings {
+        let mut settings: FmSettings = FmSettings { algorithm, feedback, ..FmSettings::default() };
+        for (operator, level) in settings.operators.iter_mut().zip(levels) {
+            operator.level = level;
+        }
+        settings
+    }
+
+    #[test]
+    fn fm_voices_are_deterministic_and_bounded() {
+        for waveform in [Waveform::Fm2, Waveform::Fm4] {
+            for algorithm in 0..5 {
+                let settings: FmSettings = fm_settings(algorithm, 1.0, [1.0, 8.0, 8.0, 1.0]);
+                let note: Vec<f32> = generate_fm(waveform, settings, 300, 220.0, 44100.0);
+
+                assert_eq!(note.len(), 13230);
+                assert_eq!(note, generate_fm(waveform, settings, 300, 220.0, 44100.0));
+                // carriers are averaged, so the output never exceeds the loudest carrier level
+                assert!(note.iter().all(|s| s.is_finite() && s.abs() <= 8.0));
+            }
+        }
+
+        let default_note: Vec<f32> = generate_fm(Waveform::Fm4, FmSettings::default(), 300, 220.0, 44100.0);
+        assert!(default_note.iter().all(|s| s.abs() <= 1.0));
     }
 
-    return output;
-}
\ No newline at end of file
+    #[test]
+    fn silent_modulators_leave_a_sine() {
+        let settings: FmSettings = fm_settings(0, 0.0, [1.0, 0.0, 0.0, 0.0]);
+        let note: Vec<f32> = generate_fm(Waveform::Fm2, settings, 100, 440.0, 44100.0);
+        let sine: Vec<f32> = generate_sine(100, 440.0, 44100.0);
+
+        assert!(note.iter().zip(sine.iter()).all(|(a, b)| (a - b).abs() < 1e-3));
+    }
+
+    #[test]
+    fn modulation_adds_sidebands() {
+        let crossings = |samples: &[f32]| samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count();
+        let plain: Vec<f32> = generate_fm(Waveform::Fm2, fm_settings(0, 0.0, [1.0, 0.0, 0.0, 0.0]), 1000, 100.0, 44100.0);
+        let bright: Vec<f32> = generate_fm(Waveform::Fm2, fm_settings(0, 0.0, [1.0, 5.0, 0.0, 0.0]), 1000, 100.0, 44100.0);
+
+        assert_eq!(crossings(&plain), 99);
+        assert!(crossings(&bright) > 150);
+    }
+
+    #[test]
+    fn settings_outside_the_ranges_are_invalid() {
+        assert!(FmSettings::default().is_valid());
+        assert!(!fm_settings(5, 0.0, [1.0; 4]).is_valid());
+        assert!(!fm_settings(0, 1.5, [1.0; 4]).is_valid());
+        assert!(!fm_settings(0, 0.0, [1.0, -1.0, 1.0, 1.0]).is_valid());
+
+        let mut settings: FmSettings = FmSettings::default();
+        settings.operators[2].ratio = 0.0;
+        assert!(!settings.is_valid());
+    }
+}
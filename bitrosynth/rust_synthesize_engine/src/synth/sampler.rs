@@ -0,0 +1,207 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use hound;
+use serde::{Deserialize, Serialize};
+
+use crate::global_state::{SAMPLES, SOUND_GENERATION};
+use crate::protracker::module_sample_data;
+use crate::render_cache::clear_row_cache;
+use crate::utils::{milliseconds_to_samples, note_to_frequency};
+
+
+// where a sample loaded from disk came from, projects store this to load it again
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleFile {
+    pub path: String,
+    pub root_note: String,
+    // frames, a loop end of 0 loops up to the end of the file
+    #[serde(default)]
+    pub loop_start: usize,
+    #[serde(default)]
+    pub loop_end: usize,
+    #[serde(default)]
+    pub one_shot: bool,
+    // set for samples embedded in a ProTracker module, the path is then the module
+    // and this the sample number from 1 to 31
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_sample: Option<usize>,
+}
+
+
+pub struct Sample {
+    pub data: Vec<f32>,
+    // rate the data plays back at when a note of root_frequency is requested
+    pub sample_rate: f32,
+    pub root_frequency: f32,
+    pub loop_range: Option<(usize, usize)>,
+    pub source: Option<SampleFile>,
+}
+
+// every channel of the file is mixed down to mono
+fn read_wav(path: &str) -> Option<(Vec<f32>, f32)> {
+    let mut reader = hound::WavReader::open(path).ok()?;
+    let spec: hound::WavSpec = reader.spec();
+    let channels: usize = spec.channels.max(1) as usize;
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<f32>, _>>().ok()?,
+        hound::SampleFormat::Int => {
+            let scale: f32 = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|v| v as f32 / scale))
+                .collect::<Result<Vec<f32>, _>>()
+                .ok()?
+        }
+    };
+
+    let data: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Some((data, spec.sample_rate as f32))
+}
+
+impl Sample {
+    pub fn load(file: SampleFile) -> Option<Sample> {
+        let (data, sample_rate): (Vec<f32>, f32) = match file.module_sample {
+            Some(number) => module_sample_data(&file.path, number)?,
+            None => read_wav(&file.path)?,
+        };
+        Sample::new(data, sample_rate, file)
+    }
+
+    // the loop and root note are taken from the file, data is already mono
+    pub fn new(data: Vec<f32>, sample_rate: f32, file: SampleFile) -> Option<Sample> {
+        let root_frequency: f32 = note_to_frequency(&file.root_note.to_ascii_uppercase())?;
+        if data.is_empty() {
+            return None;
+        }
+
+        let loop_end: usize = if file.loop_end == 0 { data.len() } else { file.loop_end.min(data.len()) };
+        if !file.one_shot && file.loop_start >= loop_end {
+            return None;
+        }
+
+        Some(Sample {
+            data,
+            sample_rate,
+            root_frequency,
+            loop_range: (!file.one_shot).then_some((file.loop_start, loop_end)),
+            source: Some(file),
+        })
+    }
+}
+
+pub struct SamplePlayer {
+    sample: Arc<Sample>,
+    position: f64,
+}
+
+impl SamplePlayer {
+    pub fn new(sample: Arc<Sample>) -> SamplePlayer {
+        SamplePlayer { sample, position: 0.0 }
+    }
+
+    fn end(&self) -> usize {
+        match self.sample.loop_range {
+            Some((_, end)) => end.min(self.sample.data.len()),
+            None => self.sample.data.len(),
+        }
+    }
+
+    fn value_at(&self, index: usize) -> f32 {
+        // interpolation past the loop end reads from the loop start
+        match self.sample.loop_range {
+            Some((start, _)) if index >= self.end() => self.sample.data.get(start).copied().unwrap_or(0.0),
+            _ => self.sample.data.get(index).copied().unwrap_or(0.0),
+        }
+    }
+
+    pub fn next_sample(&mut self, frequency: f32, sample_rate: f32) -> f32 {
+        let end: usize = self.end();
+
+        if self.position >= end as f64 {
+            match self.sample.loop_range {
+                Some((start, _)) if end > start => {
+                    let loop_length: f64 = (end - start) as f64;
+                    self.position = start as f64 + (self.position - start as f64) % loop_length;
+                }
+                // one shot samples stay silent once they have played out
+                _ => return 0.0,
+            }
+        }
+
+        let index: usize = self.position as usize;
+        let fraction: f32 = (self.position - index as f64) as f32;
+        let value: f32 = self.value_at(index) + (self.value_at(index + 1) - self.value_at(index)) * fraction;
+
+        self.position += (frequency / self.sample.root_frequency * self.sample.sample_rate / sample_rate) as f64;
+
+        value
+    }
+}
+
+
+pub fn add_sample(sample: Sample) -> Option<u16> {
+    let mut samples = SAMPLES.lock().ok()?;
+    let slot: u16 = (0..=u16::MAX).find(|slot| !samples.contains_key(slot))?;
+    samples.insert(slot, Arc::new(sample));
+    Some(slot)
+}
+
+// replaces whatever the slot held, cached rows may have used the old sample
+pub fn insert_sample(slot: u16, sample: Sample) -> bool {
+    match SAMPLES.lock() {
+        Ok(mut samples) => {
+            samples.insert(slot, Arc::new(sample));
+            SOUND_GENERATION.fetch_add(1, Ordering::SeqCst);
+        }
+        Err(_) => return false,
+    }
+    clear_row_cache();
+    true
+}
+
+// live voices read the bank as it is, renders go through their SoundBank
+pub fn get_sample(slot: u16) -> Option<Arc<Sample>> {
+    SAMPLES.lock().ok()?.get(&slot).cloned()
+}
+
+pub fn sample_files() -> Vec<(u16, SampleFile)> {
+    match SAMPLES.lock() {
+        Ok(samples) => samples
+            .iter()
+            .filter_map(|(slot, sample)| Some((*slot, sample.source.clone()?)))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// cached rows may reference the removed slots, so they go as well
+pub fn clear_samples() {
+    if let Ok(mut samples) = SAMPLES.lock() {
+        samples.clear();
+        SOUND_GENERATION.fetch_add(1, Ordering::SeqCst);
+    }
+    clear_row_cache();
+}
+
+// module samples belong to the song they were imported with, and an import replaces that song
+pub fn remove_module_samples() {
+    if let Ok(mut samples) = SAMPLES.lock() {
+        samples.retain(|_, sample| sample.source.as_ref().is_none_or(|f| f.module_sample.is_none()));
+        SOUND_GENERATION.fetch_add(1, Ordering::SeqCst);
+    }
+    clear_row_cache();
+}
+
+pub fn generate_sample(
+    player: &mut SamplePlayer,
+    milliseconds: u32,
+    frequency: f32,
+    sample_rate: f32
+) -> Vec<f32> {
+    (0..milliseconds_to_samples(milliseconds, sample_rate)).map(|_| player.next_sample(frequency, sample_rate)).collect()
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use crate::global_state::{INSTRUMENTS, SAMPLES, SOUND_GENERATION};
+
+use super::{instrument::Instrument, sampler::Sample};
+
+
+// instruments and samples note tokens are resolved against, a render takes its own copy
+// when it starts so registry calls made during the render cannot reach its worker threads
+#[derive(Clone, Default)]
+pub struct SoundBank {
+    pub instruments: BTreeMap<String, Arc<Instrument>>,
+    pub samples: BTreeMap<u16, Arc<Sample>>,
+    // registry generation the copy was taken at, None for sounds that did not come from the registry
+    pub generation: Option<u64>,
+}
+
+impl SoundBank {
+    // the registry bumps the generation under the lock it changes, so holding both
+    // locks here keeps the copy and its generation consistent
+    pub fn from_globals() -> Option<SoundBank> {
+        let instruments = INSTRUMENTS.lock().ok()?;
+        let samples = SAMPLES.lock().ok()?;
+
+        Some(SoundBank {
+            instruments: instruments.clone(),
+            samples: samples.clone(),
+            generation: Some(SOUND_GENERATION.load(Ordering::SeqCst)),
+        })
+    }
+
+    pub fn with_instruments(list: &[Instrument]) -> SoundBank {
+        SoundBank {
+            instruments: list.iter().map(|i| (i.name.clone(), Arc::new(i.clone()))).collect(),
+            ..SoundBank::default()
+        }
+    }
+
+    pub fn instrument(&self, name: &str) -> Option<Arc<Instrument>> {
+        self.instruments.get(name).cloned()
+    }
+
+    pub fn sample(&self, slot: u16) -> Option<Arc<Sample>> {
+        self.samples.get(&slot).cloned()
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+
+use crate::common_types::Waveform;
+
+use super::{channel::NoteEvent, drums::{DrumSettings, DrumVoice}, instrument::DEFAULT_DUTY, noise::PinkNoiseFilter, oscillators::{FmSettings, FmVoice, oscillator_sample, pulse_sample}, sampler::{Sample, SamplePlayer, get_sample}};
+
+
+// live pink noise cannot be peak normalised per note, this keeps it near the offline level
+const LIVE_PINK_NOISE_SCALE: f32 = 0.25;
+
+pub struct Voice {
+    waveform: Waveform,
+    frequency: f32,
+    gain: f32,
+    phase: f32,
+    pink_filter: PinkNoiseFilter,
+    rng: SmallRng,
+    sample_player: Option<SamplePlayer>,
+    duty: f32,
+    drum: Option<DrumVoice>,
+    fm: Option<FmVoice>,
+}
+
+impl Voice {
+    pub fn new(waveform: Waveform, frequency: f32, gain: f32) -> Voice {
+        let sample: Option<Arc<Sample>> = match waveform {
+            Waveform::Sample(slot) => get_sample(slot),
+            _ => None,
+        };
+        Voice::with_sample(waveform, frequency, gain, sample)
+    }
+
+    fn with_sample(waveform: Waveform, frequency: f32, gain: f32, sample: Option<Arc<Sample>>) -> Voice {
+        Voice {
+            waveform,
+            frequency,
+            gain,
+            phase: 0.0,
+            pink_filter: PinkNoiseFilter::new(),
+            rng: SmallRng::from_rng(&mut rand::rng()),
+            sample_player: sample.map(SamplePlayer::new),
+            duty: DEFAULT_DUTY,
+            drum: waveform.is_drum().then(|| DrumVoice::new(waveform, frequency, DrumSettings::default())),
+            fm: waveform.is_fm().then(|| FmVoice::new(waveform, FmSettings::default(), frequency, None)),
+        }
+    }
+
+    // takes the duty of the note's instrument, envelopes are applied by the caller,
+    // fm operator envelopes release at the end of the written note
+    pub fn for_note(event: &NoteEvent) -> Voice {
+        let mut voice: Voice = Voice::with_sample(event.waveform, event.frequency, event.gain, event.sample.clone());
+        if event.waveform.is_fm() {
+            let settings: FmSettings = event.instrument.as_ref().map_or(FmSettings::default(), |i| i.fm);
+            voice.fm = Some(FmVoice::new(event.waveform, settings, event.frequency, Some(event.milliseconds)));
+        }
+        if let Some(instrument) = &event.instrument {
+            if instrument.has_pulse_duty() {
+                voice.duty = instrument.duty;
+            }
+            if event.waveform.is_drum() {
+                voice.drum = Some(DrumVoice::new(event.waveform, event.frequency, instrument.drum));
+            }
+        }
+        voice
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    // used for tied notes, the running phase and sample position are kept
+    pub fn retune(&mut self, frequency: f32, gain: f32) {
+        self.frequency = frequency;
+        self.gain = gain;
+        if let Some(fm) = &mut self.fm {
+            fm.set_frequency(frequency);
+        }
+    }
+
+    pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
+        let value: f32 = match self.waveform {
+            Waveform::WhiteNoise => self.rng.random_range(-1.0..=1.0),
+            Waveform::PinkNoise => {
+                let white: f32 = self.rng.random_range(-1.0..=1.0);
+                (self.pink_filter.process(white) * LIVE_PINK_NOISE_SCALE).clamp(-1.0, 1.0)
+            }
+            Waveform::Silence => 0.0,
+            _ if self.waveform.is_drum() => match &mut self.drum {
+                Some(drum) => drum.next_sample(sample_rate),
+                None => 0.0,
+            },
+            Waveform::Fm2 | Waveform::Fm4 => match &mut self.fm {
+                Some(fm) => fm.next_sample(sample_rate),
+                None => 0.0,
+            },
+            Waveform::Square if self.duty != DEFAULT_DUTY => pulse_sample(self.phase, self.duty),
+            Waveform::Sample(_) => match &mut self.sample_player {
+                Some(player) => player.next_sample(self.frequency, sample_rate),
+                None => 0.0,
+            },
+            _ => oscillator_sample(self.waveform, self.phase),
+        };
+
+        self.phase = (self.phase + self.frequency / sample_rate).fract();
+
+        (value * self.gain).clamp(-1.0, 1.0)
+    }
+}
@@ -1,4 +1,5 @@
-use std::ffi::{CStr, c_char};
+use std::ffi::{CStr, c_char, c_uint};
+use std::sync::Arc;
 use std::sync::atomic::Ordering;
 
 use crate::global_state::{
@@ -6,6 +7,16 @@ use crate::global_state::{
     CURRENT_STATUS
 };
 use crate::common_types::*;
+use crate::jobs::{finish_job, start_job};
+use crate::progress::Progress;
+
+// rates the filters and oscillators can work at, anything outside is refused where it enters the engine
+pub const MIN_SAMPLE_RATE: u32 = 1000;
+pub const MAX_SAMPLE_RATE: u32 = 768_000;
+
+pub fn is_valid_sample_rate(sample_rate: u32) -> bool {
+    (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate)
+}
 
 pub fn set_status(
     status: ProcessStatus,
@@ -15,6 +26,18 @@ pub fn set_status(
     CURRENT_COMMAND.store(current_command as i32,Ordering::SeqCst);
 }
 
+// registers the job and keeps the legacy status pair in sync for older callers
+pub fn begin_job(command: CommandType) -> (u32, Arc<Progress>) {
+    let job: (u32, Arc<Progress>) = start_job(command as i32);
+    set_status(ProcessStatus::InProgress, command);
+    job
+}
+
+pub fn end_job(job_id: u32, status: ProcessStatus, error: Option<&str>) {
+    finish_job(job_id, status as i32, error);
+    set_status(status, CommandType::None);
+}
+
 pub fn c_char_to_string(c_str_ptr: *const c_char) -> Option<String> {
     if c_str_ptr.is_null() {
         return None;
@@ -28,6 +51,46 @@ pub fn c_char_to_string(c_str_ptr: *const c_char) -> Option<String> {
     }
 }
 
+pub fn c_song_to_vec(
+    data: *const *const *const c_char,
+    sizes_array: *const c_uint,
+    outer_size: c_uint,
+) -> Option<Vec<Vec<String>>> {
+    if data.is_null() || sizes_array.is_null() || outer_size == 0 {
+        return None;
+    }
+
+    let sizes: Vec<usize> = unsafe {
+        std::slice::from_raw_parts(sizes_array, outer_size as usize)
+            .iter()
+            .map(|&x| x as usize)
+            .collect()
+    };
+
+
+    let mut all_notes: Vec<Vec<String>> = Vec::with_capacity(outer_size as usize);
+
+    unsafe {
+        let data_slice = std::slice::from_raw_parts(data, outer_size as usize);
+
+        for (i, &piano_ptr) in data_slice.iter().enumerate() {
+            let inner_size = sizes[i];
+            let notes_slice = std::slice::from_raw_parts(piano_ptr, inner_size);
+            let notes_vec: Vec<String> = notes_slice
+                .iter()
+                .map(|&ptr| c_char_to_string(ptr).unwrap_or_default())
+                .collect();
+            all_notes.push(notes_vec);
+        }
+    }
+
+    Some(all_notes)
+}
+
+pub fn milliseconds_to_samples(milliseconds: u32, sample_rate: f32) -> usize {
+    (milliseconds as f32 / 1000.0 * sample_rate) as usize
+}
+
 pub fn note_to_frequency(note: &str) -> Option<f32> {
     match note {
         "C0" => Some(16.35),  "C#0" => Some(17.32), "D0" => Some(18.35),  "D#0" => Some(19.45),